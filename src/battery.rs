@@ -0,0 +1,25 @@
+use anyhow::Result;
+use esp_idf_hal::adc::config::Config;
+use esp_idf_hal::adc::{attenuation, AdcChannelDriver, AdcDriver, ADC1};
+use esp_idf_hal::gpio::ADCPin;
+
+/// Reads the voltage at an ADC pin in mV, for the battery monitor.
+///
+/// Only ADC1 pins (32 to 39) work, ADC2 is taken by the radio.
+pub struct BatteryAdc(Box<dyn FnMut() -> Result<u16> + Send>);
+
+impl BatteryAdc {
+    pub fn new<P>(adc: ADC1, pin: P) -> Result<Self>
+    where
+        P: ADCPin<Adc = ADC1> + 'static,
+    {
+        // Calibrated, so readings are in mV. 11 dB measures up to about 3.1V.
+        let mut driver = AdcDriver::new(adc, &Config::new().calibration(true))?;
+        let mut channel: AdcChannelDriver<{ attenuation::DB_11 }, P> = AdcChannelDriver::new(pin)?;
+        Ok(BatteryAdc(Box::new(move || Ok(driver.read(&mut channel)?))))
+    }
+
+    pub fn read(&mut self) -> Result<u16> {
+        (self.0)()
+    }
+}
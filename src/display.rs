@@ -0,0 +1,216 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, Result};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use esp_idf_hal::gpio::{AnyOutputPin, Output, PinDriver};
+use esp_idf_hal::spi::{SpiDeviceDriver, SpiDriver};
+use esp_idf_svc::hal::i2c::I2cDriver;
+use ssd1306::mode::{BufferedGraphicsMode, DisplayConfig};
+use ssd1306::prelude::{Brightness, DisplayRotation, SPIInterfaceNoCS, WriteOnlyDataCommand};
+use ssd1306::size::{DisplaySize, DisplaySize128x32, DisplaySize128x64};
+use ssd1306::{I2CDisplayInterface, Ssd1306};
+use vogon::screen::{Controller, Panel, Screen};
+
+/// A panel driver that keeps what is drawn in a buffer until it is flushed.
+pub trait Driver {
+    fn size(&self) -> Size;
+    fn set_pixel(&mut self, x: u32, y: u32, on: bool);
+    fn clear(&mut self, color: BinaryColor);
+    /// Write the buffer to the panel.
+    fn flush(&mut self) -> Result<()>;
+    fn set_contrast(&mut self, contrast: u8) -> Result<()>;
+    fn set_on(&mut self, on: bool) -> Result<()>;
+}
+
+impl<DI, SIZE> Driver for Ssd1306<DI, SIZE, BufferedGraphicsMode<SIZE>>
+where
+    DI: WriteOnlyDataCommand,
+    SIZE: DisplaySize,
+{
+    fn size(&self) -> Size {
+        OriginDimensions::size(self)
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        Ssd1306::set_pixel(self, x, y, on)
+    }
+
+    fn clear(&mut self, color: BinaryColor) {
+        DrawTarget::clear(self, color).unwrap();
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ssd1306::flush(self).map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<()> {
+        // The precharge period of the default brightness
+        self.set_brightness(Brightness::custom(0x2, contrast))
+            .map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+
+    fn set_on(&mut self, on: bool) -> Result<()> {
+        self.set_display_on(on)
+            .map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+}
+
+#[cfg(feature = "sh1106")]
+impl<DI> Driver for sh1106::mode::GraphicsMode<DI>
+where
+    DI: sh1106::interface::DisplayInterface,
+    DI::Error: std::fmt::Debug,
+{
+    fn size(&self) -> Size {
+        let (width, height) = self.get_dimensions();
+        Size::new(width as u32, height as u32)
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        sh1106::mode::GraphicsMode::set_pixel(self, x, y, on as u8)
+    }
+
+    fn clear(&mut self, color: BinaryColor) {
+        match color {
+            BinaryColor::Off => sh1106::mode::GraphicsMode::clear(self),
+            BinaryColor::On => DrawTarget::clear(self, color).unwrap(),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        sh1106::mode::GraphicsMode::flush(self).map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<()> {
+        sh1106::mode::GraphicsMode::set_contrast(self, contrast)
+            .map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+
+    fn set_on(&mut self, on: bool) -> Result<()> {
+        self.display_on(on)
+            .map_err(|e| anyhow!("Display error: {:?}", e))
+    }
+}
+
+/// How the panel is wired up. Panels on SPI have a data/command pin, chip
+/// select is handled by the SPI driver.
+pub enum Connection {
+    I2c(I2cDriver<'static>),
+    Spi {
+        spi: SpiDeviceDriver<'static, SpiDriver<'static>>,
+        dc: PinDriver<'static, AnyOutputPin, Output>,
+    },
+}
+
+/// The OLED, wrapped so it can implement `Screen` whatever the panel is.
+pub struct Display(Box<dyn Driver>);
+
+impl Display {
+    pub fn new(panel: Panel, connection: Connection) -> Result<Self> {
+        let driver = match (panel.controller, panel.size.height) {
+            (Controller::Ssd1306, 64) => ssd1306(DisplaySize128x64, connection)?,
+            (Controller::Ssd1306, _) => ssd1306(DisplaySize128x32, connection)?,
+            #[cfg(feature = "sh1106")]
+            (Controller::Sh1106, _) => sh1106(connection)?,
+            #[cfg(not(feature = "sh1106"))]
+            (Controller::Sh1106, _) => {
+                return Err(anyhow!("Built without the sh1106 feature, {:?}", panel))
+            }
+        };
+        Ok(Display(driver))
+    }
+}
+
+fn ssd1306<SIZE>(size: SIZE, connection: Connection) -> Result<Box<dyn Driver>>
+where
+    SIZE: DisplaySize + 'static,
+{
+    match connection {
+        Connection::I2c(i2c) => init_ssd1306(I2CDisplayInterface::new(i2c), size),
+        Connection::Spi { spi, dc } => init_ssd1306(SPIInterfaceNoCS::new(spi, dc), size),
+    }
+}
+
+fn init_ssd1306<DI, SIZE>(di: DI, size: SIZE) -> Result<Box<dyn Driver>>
+where
+    DI: WriteOnlyDataCommand + 'static,
+    SIZE: DisplaySize + 'static,
+{
+    let mut driver = Ssd1306::new(di, size, DisplayRotation::Rotate0).into_buffered_graphics_mode();
+    driver
+        .init()
+        .map_err(|e| anyhow!("Display error: {:?}", e))?;
+    Ok(Box::new(driver))
+}
+
+#[cfg(feature = "sh1106")]
+fn sh1106(connection: Connection) -> Result<Box<dyn Driver>> {
+    use sh1106::builder::{Builder, NoOutputPin};
+    use sh1106::mode::GraphicsMode;
+
+    let builder = Builder::new().with_size(sh1106::displaysize::DisplaySize::Display128x64);
+    let driver: Box<dyn Driver> = match connection {
+        Connection::I2c(i2c) => {
+            let mut driver: GraphicsMode<_> = builder.connect_i2c(i2c).into();
+            driver
+                .init()
+                .map_err(|e| anyhow!("Display error: {:?}", e))?;
+            Box::new(driver)
+        }
+        Connection::Spi { spi, dc } => {
+            let mut driver: GraphicsMode<_> =
+                builder.connect_spi(spi, dc, NoOutputPin::new()).into();
+            driver
+                .init()
+                .map_err(|e| anyhow!("Display error: {:?}", e))?;
+            Box::new(driver)
+        }
+    };
+    Ok(driver)
+}
+
+impl OriginDimensions for Display {
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+}
+
+impl DrawTarget for Display {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let size = self.0.size();
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) {
+                if x < size.width && y < size.height {
+                    self.0.set_pixel(x, y, color.is_on());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.0.clear(color);
+        Ok(())
+    }
+}
+
+impl Screen for Display {
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<()> {
+        self.0.set_contrast(contrast)
+    }
+
+    fn set_on(&mut self, on: bool) -> Result<()> {
+        self.0.set_on(on)
+    }
+}
@@ -0,0 +1,55 @@
+//! ESP-NOW receive callback that also passes on the signal strength, which
+//! `EspNow::register_recv_cb` leaves out, and broadcasts that reach badges
+//! with a sleeping radio.
+use std::ffi::c_int;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use esp_idf_hal::sys::esp;
+use esp_idf_svc::espnow::{EspNow, BROADCAST};
+use esp_idf_sys::{esp_now_recv_info_t, esp_now_register_recv_cb};
+use vogon::peers::Mac;
+use vogon::power::RESEND_AFTER;
+
+type RecvCallback = Box<dyn FnMut(Mac, Option<i8>, &[u8]) + Send>;
+
+static RECV_CB: Mutex<Option<RecvCallback>> = Mutex::new(None);
+
+/// Register a callback that receives the MAC address of the sender, the RSSI
+/// in dBm and the data of every frame.
+///
+/// ESP-NOW has to be initialized with `EspNow::take` first.
+pub fn register_recv_cb<F>(cb: F) -> Result<()>
+where
+    F: FnMut(Mac, Option<i8>, &[u8]) + Send + 'static,
+{
+    *RECV_CB.lock().unwrap() = Some(Box::new(cb));
+    esp!(unsafe { esp_now_register_recv_cb(Some(recv_cb)) })?;
+    Ok(())
+}
+
+/// Broadcast `frames`, and again after `RESEND_AFTER` for badges whose radio
+/// was asleep the first time. Receivers drop the copies they already have.
+pub fn broadcast(esp_now: &EspNow, frames: &[Vec<u8>]) -> Result<()> {
+    for frame in frames {
+        esp_now.send(BROADCAST, frame)?;
+    }
+    std::thread::sleep(RESEND_AFTER);
+    for frame in frames {
+        esp_now.send(BROADCAST, frame)?;
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn recv_cb(info: *const esp_now_recv_info_t, data: *const u8, len: c_int) {
+    let info = &*info;
+    let mac: Mac = std::slice::from_raw_parts(info.src_addr, 6)
+        .try_into()
+        .unwrap();
+    let rssi = info.rx_ctrl.as_ref().map(|rx_ctrl| rx_ctrl.rssi() as i8);
+    let data = std::slice::from_raw_parts(data, len as usize);
+
+    if let Some(cb) = RECV_CB.lock().unwrap().as_mut() {
+        cb(mac, rssi, data);
+    }
+}
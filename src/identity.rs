@@ -0,0 +1,99 @@
+use anyhow::Result;
+use esp_idf_hal::sys::esp;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use vogon::identity::{default_name, DeviceId};
+
+/// Id to provision badges with on first boot, if they were built with one.
+const DEVICE_ID: Option<&str> = option_env!("DEVICE_ID");
+/// Whether to switch ids when another badge uses ours, "1" to enable.
+const AUTO_RESOLVE_ID: Option<&str> = option_env!("AUTO_RESOLVE_ID");
+
+const NAMESPACE: &str = "identity";
+const KEY_ID: &str = "id";
+const KEY_NAME: &str = "name";
+const MAX_NAME_LEN: usize = 32;
+
+pub struct Identity {
+    pub id: DeviceId,
+    pub name: String,
+    pub mac: [u8; 6],
+}
+
+/// The badge's identity, persisted in NVS so every badge can run the same build.
+pub struct IdentityStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl IdentityStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        Ok(IdentityStore {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    /// Read the identity from NVS, provisioning it on first boot.
+    ///
+    /// The id is taken from the `DEVICE_ID` the firmware was built with, or
+    /// derived from the MAC address if it was built without one. An invalid
+    /// `DEVICE_ID` is an error.
+    pub fn load(&mut self) -> Result<Identity> {
+        let mac = mac_address()?;
+        // Checked on every boot, so a bad build doesn't go unnoticed on badges
+        // that were provisioned before
+        let build_id: Option<DeviceId> = match DEVICE_ID {
+            Some(s) => Some(
+                s.parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid DEVICE_ID: {}", e))?,
+            ),
+            None => None,
+        };
+
+        let id = match self.nvs.get_u8(KEY_ID)?.map(DeviceId::new) {
+            Some(Ok(id)) => id,
+            stored => {
+                if let Some(Err(e)) = stored {
+                    log::warn!("Ignoring stored device id: {}", e);
+                }
+                let id = build_id.unwrap_or_else(|| DeviceId::from_mac(&mac));
+                log::info!("Provisioning device id {}", id);
+                self.set_id(id)?;
+                id
+            }
+        };
+
+        let mut buf = [0u8; MAX_NAME_LEN + 1];
+        let name = match self.nvs.get_str(KEY_NAME, &mut buf)? {
+            Some(name) => name.to_string(),
+            None => {
+                let name = default_name(&mac);
+                self.set_name(&name)?;
+                name
+            }
+        };
+
+        Ok(Identity { id, name, mac })
+    }
+
+    pub fn set_id(&mut self, id: DeviceId) -> Result<()> {
+        self.nvs.set_u8(KEY_ID, id.get())?;
+        Ok(())
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Result<()> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            anyhow::bail!("Name must be 1 to {} bytes", MAX_NAME_LEN);
+        }
+        self.nvs.set_str(KEY_NAME, name)?;
+        Ok(())
+    }
+}
+
+pub fn auto_resolve_id() -> bool {
+    AUTO_RESOLVE_ID == Some("1")
+}
+
+fn mac_address() -> Result<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    esp!(unsafe { esp_idf_sys::esp_efuse_mac_get_default(mac.as_mut_ptr()) })?;
+    Ok(mac)
+}
@@ -1,52 +1,100 @@
-use anyhow::Result;
-use embedded_graphics::image::{Image, ImageRaw};
-use embedded_graphics::mono_font::{ascii::FONT_5X7, MonoTextStyle};
-use embedded_graphics::pixelcolor::*;
-use embedded_graphics::prelude::*;
-use embedded_graphics::text::{Alignment, Baseline, LineHeight, Text, TextStyleBuilder};
+use anyhow::{anyhow, Result};
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_hal::gpio::AnyIOPin;
 use esp_idf_hal::gpio::PinDriver;
+use esp_idf_hal::spi::{self, SpiDeviceDriver};
 use esp_idf_hal::sys::esp;
+use esp_idf_hal::uart::{self, UartDriver};
 use esp_idf_svc::espnow::{EspNow, PeerInfo, BROADCAST};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::hal::cpu::Core;
-use esp_idf_svc::hal::i2c::I2cDriver;
 use esp_idf_svc::hal::prelude::*;
 use esp_idf_svc::hal::{i2c, peripherals::Peripherals};
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
 use esp_idf_svc::wifi::EspWifi;
 use esp_idf_sys as _;
 use rand::Rng;
-use ssd1306::mode::{BufferedGraphicsMode, DisplayConfig};
-use ssd1306::prelude::I2CInterface;
-use ssd1306::{rotation::DisplayRotation, size::DisplaySize128x32, Ssd1306};
 use std::sync::{Arc, Mutex};
-use utils::{screen_center, set_thread_spawn_configuration};
-
-use crate::utils::mac_to_string;
-
-mod effects;
+use std::time::{Duration, Instant};
+use utils::set_thread_spawn_configuration;
+use vogon::assets::ASCII_CHEWIE;
+use vogon::battery::{Battery, BatteryMonitor};
+use vogon::corpus;
+use vogon::library::Library;
+use vogon::peers::{Mac, DEFAULT_PEER_TIMEOUT};
+use vogon::playback::Policy;
+use vogon::power::Profile;
+use vogon::protection::Protection;
+use vogon::protocol::{Message, Packet};
+use vogon::relay::RELAY_DELAY_RANGE;
+use vogon::screen::{Interface, Panel, Rotated};
+use vogon::selector::Strategy;
+use vogon::transceiver::{self, Badge, Broadcaster, Poem, PoemText, STATUS_INTERVAL};
+use vogon::utils::mac_to_string;
+use vogon::TOTAL_DEVICES;
+
+use crate::battery::BatteryAdc;
+use crate::display::{Connection, Display};
+use crate::identity::{auto_resolve_id, IdentityStore};
+use crate::settings::SettingsStore;
+use crate::shell::Shell;
+
+mod battery;
+mod display;
+mod espnow;
+mod identity;
+mod power;
+mod settings;
+mod shell;
+mod storage;
 mod utils;
 
-const DEVICE_ID: &str = env!("DEVICE_ID");
-const TOTAL_DEVICES: u8 = 42;
-const SCREEN_WIDTH: u32 = 128;
-const SCREEN_HEIGHT: u32 = 32;
 const ESP_NOW_CHANNEL: u8 = 1;
-
-const SEND_DELAY_RANGE: std::ops::Range<u64> = 5..20;
-
-const POETRY: &[u8; 18827] = include_bytes!("../assets/poetry.txt");
-const ASCII_CHEWIE: &[u8; 2806] = include_bytes!("../assets/chewie.txt");
-type Display = Ssd1306<
-    I2CInterface<I2cDriver<'static>>,
-    DisplaySize128x32,
-    BufferedGraphicsMode<DisplaySize128x32>,
->;
-
-struct Poem {
-    id: u8,
-    src: u8,
-}
+/// How often the battery is measured.
+const BATTERY_INTERVAL: Duration = Duration::from_secs(5);
+/// Relay received poems with this TTL, "0" disables relaying.
+const RELAY_TTL: Option<&str> = option_env!("RELAY_TTL");
+/// Whether poems that were received as text are added to the library, "1" to
+/// enable.
+const STORE_RECEIVED: Option<&str> = option_env!("STORE_RECEIVED");
+/// Number of received poems that wait to be shown, older ones are dropped.
+const QUEUE_LENGTH: Option<&str> = option_env!("QUEUE_LENGTH");
+/// Whether received poems are shown before random ones, "1" to enable.
+const RECEIVED_FIRST: Option<&str> = option_env!("RECEIVED_FIRST");
+/// Whether a received poem interrupts a random one, "1" to enable.
+const INTERRUPT_RANDOM: Option<&str> = option_env!("INTERRUPT_RANDOM");
+/// Seconds within which a poem that was received before is skipped.
+const REPEAT_WINDOW: Option<&str> = option_env!("REPEAT_WINDOW");
+/// Seconds within which a second poem from the same badge is skipped.
+const SOURCE_INTERVAL: Option<&str> = option_env!("SOURCE_INTERVAL");
+/// Number of shown poems that random poems avoid.
+const HISTORY_SIZE: Option<&str> = option_env!("HISTORY_SIZE");
+/// How poems are picked, see `Strategy`'s `FromStr`.
+const SELECTION: Option<&str> = option_env!("SELECTION");
+/// The OLED panel, see `Panel`'s `FromStr`.
+const PANEL: Option<&str> = option_env!("PANEL");
+/// Pixels the status screens move around, so they don't burn in.
+const PIXEL_SHIFT: Option<&str> = option_env!("PIXEL_SHIFT");
+/// Seconds the status screens stay at the same pixels.
+const SHIFT_INTERVAL: Option<&str> = option_env!("SHIFT_INTERVAL");
+/// Contrast of the display, 0..255.
+const CONTRAST: Option<&str> = option_env!("CONTRAST");
+/// Contrast of the display when no poem was received for a while.
+const DIMMED_CONTRAST: Option<&str> = option_env!("DIMMED_CONTRAST");
+/// Seconds without a poem after which the display is dimmed, "0" to never dim.
+const DIM_AFTER: Option<&str> = option_env!("DIM_AFTER");
+/// Seconds without a poem after which the display is turned off, "0" to keep
+/// it on.
+const SCREEN_OFF_AFTER: Option<&str> = option_env!("SCREEN_OFF_AFTER");
+/// Power profile of badges that weren't given one on the console, "parade"
+/// or "storage".
+const POWER_PROFILE: Option<&str> = option_env!("POWER_PROFILE");
+/// ADC1 pin the battery is measured on, 32 to 36 or 39, "0" if it isn't.
+const BATTERY_PIN: Option<&str> = option_env!("BATTERY_PIN");
+/// Battery voltage over the voltage at `BATTERY_PIN`.
+const BATTERY_DIVIDER: Option<&str> = option_env!("BATTERY_DIVIDER");
+/// Battery charge in percent below which the badge saves power.
+const LOW_BATTERY: Option<&str> = option_env!("LOW_BATTERY");
 
 fn main() -> Result<()> {
     // It is necessary to call this function once. Otherwise some patches to the runtime
@@ -56,189 +104,245 @@ fn main() -> Result<()> {
     // Bind the log crate to the ESP Logging facilities
     esp_idf_svc::log::EspLogger::initialize_default();
 
-    // Split POETRY on empty lines and put them in an array
-    let poems: Vec<String> = std::str::from_utf8(POETRY)
-        .unwrap()
-        .split("\n\n")
-        .map(|s| s.to_string())
-        .collect();
-
-    let poems_len = poems.len();
-
     log::info!("\n{}", std::str::from_utf8(ASCII_CHEWIE).unwrap());
 
     log::info!("Chewbacchus 2023 - Vogon Poetry Transceiver");
     log::info!("by: Wouter de Bie - wouter@evenflow.nl");
-    log::info!("Number of poems: {}", poems.len());
-    log::info!("Device ID: {}/{}", DEVICE_ID, TOTAL_DEVICES);
+
+    // The poems the badge was built with are added to the library on first
+    // boot and after an update that has new ones
+    let poems = corpus::poems();
+    let library =
+        match storage::mount().and_then(|_| Library::open(storage::POEMS_PATH, poems.clone())) {
+            Ok(library) => library,
+            Err(e) => {
+                log::warn!("Poem library unavailable, using the embedded poems: {}", e);
+                Library::new(poems)
+            }
+        };
+    log::info!("Number of poems: {}", library.len());
+
+    let sysloop = EspSystemEventLoop::take()?;
+    let nvs = EspDefaultNvsPartition::take()?;
+    let mut store = IdentityStore::new(nvs.clone())?;
+    let identity = store.load()?;
+    let device_id = identity.id;
+    log::info!(
+        "Device ID: {}/{} ({})",
+        device_id,
+        TOTAL_DEVICES,
+        identity.name
+    );
+
+    let settings = SettingsStore::new(nvs.clone())?;
+    let orientation = Arc::new(Mutex::new(settings.orientation()?));
+    log::info!("Orientation: {:?}", orientation.lock().unwrap());
+    let profile = match POWER_PROFILE {
+        Some(s) => settings.profile(s.parse()?)?,
+        None => settings.profile(Profile::default())?,
+    };
+    let power_mode = profile.mode();
+    power::configure_cpu(&power_mode)?;
 
     let peripherals = Peripherals::take().unwrap();
     let led = peripherals.pins.gpio22;
-    let sda = peripherals.pins.gpio0;
-    let scl = peripherals.pins.gpio4;
 
-    let di = ssd1306::I2CDisplayInterface::new(
-        i2c::I2cDriver::new(
+    let panel: Panel = match PANEL {
+        Some(s) => s.parse()?,
+        None => Panel::default(),
+    };
+    log::info!("Panel: {:?}", panel);
+    let connection = match panel.interface {
+        Interface::I2c => Connection::I2c(i2c::I2cDriver::new(
             peripherals.i2c0,
-            sda,
-            scl,
+            peripherals.pins.gpio0,
+            peripherals.pins.gpio4,
             &i2c::I2cConfig::new().baudrate(1000.kHz().into()),
+        )?),
+        Interface::Spi => {
+            // Reset the panel, which I2C panels do by themselves
+            let mut rst = PinDriver::output(peripherals.pins.gpio17)?;
+            rst.set_low()?;
+            FreeRtos::delay_ms(10);
+            rst.set_high()?;
+            // Dropping the driver would let the pin float
+            std::mem::forget(rst);
+            Connection::Spi {
+                spi: SpiDeviceDriver::new_single(
+                    peripherals.spi2,
+                    peripherals.pins.gpio18,
+                    peripherals.pins.gpio23,
+                    Option::<AnyIOPin>::None,
+                    Some(peripherals.pins.gpio5),
+                    &spi::SpiDriverConfig::new(),
+                    &spi::config::Config::new().baudrate(10.MHz().into()),
+                )?,
+                dc: PinDriver::output(peripherals.pins.gpio16.into())?,
+            }
+        }
+    };
+
+    let invalid_pin = |pin: &str| {
+        anyhow!(
+            "Invalid BATTERY_PIN {:?}, expected 0 or one of the pins 32 to 36 and 39",
+            pin
         )
-        .unwrap(),
-    );
+    };
+    let battery_pin = match BATTERY_PIN {
+        Some(s) => s.trim().parse::<u8>().map_err(|_| invalid_pin(s))?,
+        None => 0,
+    };
+    let battery_adc = match battery_pin {
+        0 => None,
+        32 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio32)?),
+        33 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio33)?),
+        34 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio34)?),
+        35 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio35)?),
+        36 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio36)?),
+        39 => Some(BatteryAdc::new(peripherals.adc1, peripherals.pins.gpio39)?),
+        pin => return Err(invalid_pin(&pin.to_string())),
+    };
+    let defaults = Battery::default();
+    let battery = Battery {
+        divider: BATTERY_DIVIDER
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.divider),
+        low: LOW_BATTERY
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.low),
+        ..defaults
+    };
 
     let (tx, rx) = std::sync::mpsc::channel::<Poem>();
-
-    let received = Arc::new(Mutex::new(0));
-    let sent = Arc::new(Mutex::new(0));
+    let (relay_tx, relay_rx) = std::sync::mpsc::channel::<Message>();
+
+    let start = Instant::now();
+    let mut badge = Badge::new(device_id, identity.mac, library, DEFAULT_PEER_TIMEOUT);
+    badge.auto_resolve = auto_resolve_id();
+    badge.relay_ttl = RELAY_TTL
+        .and_then(|ttl| ttl.parse().ok())
+        .filter(|ttl| *ttl > 0);
+    badge.store_received = STORE_RECEIVED == Some("1");
+    let selection: Strategy = match SELECTION {
+        Some(s) => s.parse()?,
+        None => Strategy::default(),
+    };
+    let defaults = Policy::default();
+    let policy = Policy {
+        max_queue: QUEUE_LENGTH
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.max_queue),
+        received_first: RECEIVED_FIRST.map_or(defaults.received_first, |v| v == "1"),
+        interrupt_random: INTERRUPT_RANDOM.map_or(defaults.interrupt_random, |v| v == "1"),
+        repeat_window: REPEAT_WINDOW
+            .and_then(|s| s.parse().ok())
+            .map_or(defaults.repeat_window, Duration::from_secs),
+        source_interval: SOURCE_INTERVAL
+            .and_then(|s| s.parse().ok())
+            .map_or(defaults.source_interval, Duration::from_secs),
+        history: HISTORY_SIZE
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.history),
+        selection: selection.clone(),
+    };
+    let defaults = Protection::default();
+    let mut protection = Protection {
+        shift: PIXEL_SHIFT
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.shift),
+        shift_interval: SHIFT_INTERVAL
+            .and_then(|s| s.parse().ok())
+            .map_or(defaults.shift_interval, Duration::from_secs),
+        contrast: CONTRAST
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.contrast),
+        dimmed_contrast: DIMMED_CONTRAST
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(defaults.dimmed_contrast),
+        dim_after: match DIM_AFTER.and_then(|s| s.parse().ok()) {
+            Some(0) => None,
+            Some(s) => Some(Duration::from_secs(s)),
+            None => defaults.dim_after,
+        },
+        off_after: match SCREEN_OFF_AFTER.and_then(|s| s.parse().ok()) {
+            Some(0) => None,
+            Some(s) => Some(Duration::from_secs(s)),
+            None => defaults.off_after,
+        },
+    };
+    if !power_mode.display {
+        protection.off_after = Some(Duration::ZERO);
+    }
+    log::info!(
+        "Power profile {}: {}",
+        profile,
+        power_mode.budget(protection.contrast)
+    );
+    let badge = Arc::new(badge);
+    let mut broadcaster = Broadcaster::new(device_id, selection);
+    broadcaster.send_delay = power_mode.send_delay.clone();
+    let broadcaster = Arc::new(Mutex::new(broadcaster));
+
+    // Measure the battery, and save power when it runs low
+    if let Some(mut adc) = battery_adc {
+        set_thread_spawn_configuration("battery-thread\0", 4096, 5, None)?;
+        let battery_badge = badge.clone();
+        let battery_broadcaster = broadcaster.clone();
+        let send_delay = power_mode.send_delay.clone();
+        let mut monitor = BatteryMonitor::new(battery);
+        std::thread::Builder::new()
+            .stack_size(4096)
+            .spawn(move || {
+                let mut low = false;
+                loop {
+                    match adc.read() {
+                        Ok(millivolts) => {
+                            let level = monitor.sample(millivolts);
+                            if level.low != low {
+                                low = level.low;
+                                log::warn!(
+                                    "Battery {}% ({}mV), {}",
+                                    level.percent,
+                                    level.millivolts,
+                                    if low {
+                                        "saving power"
+                                    } else {
+                                        "back to normal"
+                                    }
+                                );
+                                battery_broadcaster.lock().unwrap().send_delay =
+                                    monitor.battery().send_delay(&send_delay, low);
+                            }
+                            *battery_badge.battery.lock().unwrap() = Some(level);
+                        }
+                        Err(e) => log::warn!("Can't read the battery: {}", e),
+                    }
+                    std::thread::sleep(BATTERY_INTERVAL);
+                }
+            })?;
+    }
 
     // Spawn display thread on core 1
     set_thread_spawn_configuration("display-thread\0", 8196, 5, Some(Core::Core1))?;
-    let display_received = received.clone();
-    let display_sent = sent.clone();
+    let display_badge = badge.clone();
+    let display_nvs = nvs.clone();
+    let display_orientation = orientation.clone();
     let display_thread = std::thread::Builder::new()
         .stack_size(8196)
         .spawn(move || {
-            let mut display = Ssd1306::new(di, DisplaySize128x32, DisplayRotation::Rotate0)
-                .into_buffered_graphics_mode();
-
-            display
-                .init()
-                .map_err(|e| anyhow::anyhow!("Display error: {:?}", e))
-                .unwrap();
-
-            // Logo
-            display.clear(BinaryColor::Off).unwrap();
-            let logotype: ImageRaw<BinaryColor> =
-                ImageRaw::new(include_bytes!("../assets/logotype.raw"), 128);
-
-            let mut image = Image::new(&logotype, Point::new(0, 0));
-
-            // Show logo 3 times
-            for _ in 0..3 {
-                effects::up_in(&mut display, &mut image).unwrap();
-                // std::thread::sleep(std::time::Duration::from_secs(1));
-                effects::up_out(&mut display, &mut image).unwrap();
-            }
-
-            display.clear(BinaryColor::Off).unwrap();
-            let character_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
-
-            let text_style = TextStyleBuilder::new()
-                .alignment(Alignment::Center)
-                .line_height(LineHeight::Percent(150))
-                .baseline(Baseline::Top)
-                .build();
-
-            let mut sub_logo_text = Text::with_text_style(
-                "XIII\nNothing To See Here",
-                Point::new(0, 0),
-                character_style,
-                text_style,
-            );
-            sub_logo_text.translate_mut(
-                screen_center(&sub_logo_text) - sub_logo_text.bounding_box().top_left,
-            );
-            sub_logo_text.draw(&mut display).unwrap();
-            display.flush().unwrap();
-            std::thread::sleep(std::time::Duration::from_secs(4));
-
-            let mut boot_text = Text::with_text_style(
-                "Vogon Poetry Transceiver\nVersion: 0x42\nBooting..",
-                Point::new(0, 0),
-                character_style,
-                text_style,
+            let mut display = Rotated::new(
+                Display::new(panel, connection).unwrap(),
+                display_orientation,
             );
+            let id = transceiver::run(&mut display, &display_badge, &rx, policy, protection, start)
+                .unwrap();
 
-            // Since the alignment is center, the bounding box is moved to the left,
-            // so we move it to 0,0 and then translate it to the calculated center
-            boot_text.translate_mut(screen_center(&boot_text) - boot_text.bounding_box().top_left);
-
-            effects::blink(
-                &mut display,
-                &mut boot_text,
-                3,
-                std::time::Duration::from_millis(500),
-                true,
-            )
-            .unwrap();
-
-            std::thread::sleep(std::time::Duration::from_secs(2));
-            // system time now
-            let mut last_received = std::time::SystemTime::now();
-            loop {
-                display.clear(BinaryColor::Off).unwrap();
-                let wait = format!(
-                    "Device {}/{}\nWaiting for Poetry..\nReceived: {}, sent: {}",
-                    DEVICE_ID,
-                    TOTAL_DEVICES,
-                    display_received.lock().unwrap(),
-                    display_sent.lock().unwrap()
-                );
-                let mut wait_text =
-                    Text::with_text_style(&wait, Point::new(0, 0), character_style, text_style);
-                wait_text
-                    .translate_mut(screen_center(&wait_text) - wait_text.bounding_box().top_left);
-                wait_text.draw(&mut display).unwrap();
-                display.flush().unwrap();
-
-                // Sleep 2 second
-                std::thread::sleep(std::time::Duration::from_secs(4));
-
-                // Wait for messages, but timeout after 1 second
-                let msg = rx.recv_timeout(std::time::Duration::from_secs(1));
-
-                if let Ok(poem) = msg {
-                    last_received = std::time::SystemTime::now();
-                    let src = poem.src;
-                    display_poem(
-                        poem,
-                        &mut display,
-                        &poems,
-                        format!("Received from: {}/{}\n", src, TOTAL_DEVICES).as_str(),
-                    );
-                }
-                // duration since last message
-                else if std::time::SystemTime::now()
-                    .duration_since(last_received)
-                    .unwrap()
-                    .as_secs()
-                    > 10
-                {
-                    display.clear(BinaryColor::Off).unwrap();
-                    log::info!("No poem received in the last 10 seconds..");
-                    let mut no_poem_text = Text::with_text_style(
-                        "No poem received in\nthe last 10 seconds..\nRandomly picking one..",
-                        Point::new(0, 0),
-                        character_style,
-                        text_style,
-                    );
-                    no_poem_text.translate_mut(
-                        screen_center(&no_poem_text) - no_poem_text.bounding_box().top_left,
-                    );
-                    no_poem_text.draw(&mut display).unwrap();
-                    display.flush().unwrap();
-
-                    std::thread::sleep(std::time::Duration::from_secs(4));
-
-                    // Pick random poem
-                    let poem_id = rand::thread_rng().gen_range(0..poems_len) as u8;
-                    display_poem(
-                        Poem {
-                            id: poem_id,
-                            src: DEVICE_ID.parse().unwrap(),
-                        },
-                        &mut display,
-                        &poems,
-                        "Random poem:\n",
-                    );
-                }
-            }
+            // Another badge uses our id, switch to the one that was picked
+            IdentityStore::new(display_nvs).unwrap().set_id(id).unwrap();
+            unsafe { esp_idf_sys::esp_restart() };
         })?;
 
     // Setup ESP-NOW
-    let sysloop = EspSystemEventLoop::take()?;
-    let nvs = EspDefaultNvsPartition::take()?;
     let mut wifi = Box::new(EspWifi::new(peripherals.modem, sysloop, Some(nvs)).unwrap());
 
     esp!(unsafe { esp_idf_sys::esp_wifi_set_mode(esp_idf_sys::wifi_mode_t_WIFI_MODE_STA) })
@@ -253,6 +357,7 @@ fn main() -> Result<()> {
     .unwrap();
 
     wifi.start()?;
+    power::configure_radio(&power_mode)?;
 
     let esp_now = Arc::new(EspNow::take().unwrap());
     esp_now
@@ -265,18 +370,59 @@ fn main() -> Result<()> {
         })
         .unwrap();
 
+    let recv_badge = badge.clone();
+    let recv_broadcaster = broadcaster.clone();
     let tx_recv = tx.clone();
-    let esp_now_recv_cb = move |src: &[u8], data: &[u8]| {
-        log::info!("Data recv from {}, len {}", mac_to_string(src), data.len());
-        let recv_data = Poem {
-            id: data[0],
-            src: data[1],
-        };
-        tx_recv.send(recv_data).unwrap();
-        let mut r = received.lock().unwrap();
-        *r += 1;
+    let esp_now_recv_cb = move |src: Mac, rssi: Option<i8>, data: &[u8]| {
+        log::info!(
+            "Data recv from {}, len {}, rssi {:?}",
+            mac_to_string(&src),
+            data.len(),
+            rssi
+        );
+        if let Some(poem) = recv_badge.receive(src, rssi, data, start.elapsed()) {
+            // The other threads are gone while the badge restarts, drop
+            // what they would have handled
+            if let Some(message) = recv_badge.relay(&poem) {
+                if let Err(e) = relay_tx.send(message) {
+                    log::warn!("Dropped {:?}, nothing relays it", e.0);
+                }
+            }
+            if let PoemText::Library(id) = poem.text {
+                recv_broadcaster.lock().unwrap().seen(id);
+            }
+            if let Err(e) = tx_recv.send(poem) {
+                log::warn!("Dropped the poem from {}, nothing shows it", e.0.src);
+            }
+        }
     };
-    esp_now.register_recv_cb(esp_now_recv_cb).unwrap();
+    espnow::register_recv_cb(esp_now_recv_cb).unwrap();
+
+    // Spawn the serial console
+    set_thread_spawn_configuration("shell-thread\0", 8196, 5, None)?;
+    let shell = Shell {
+        uart: UartDriver::new(
+            peripherals.uart0,
+            peripherals.pins.gpio1,
+            peripherals.pins.gpio3,
+            Option::<AnyIOPin>::None,
+            Option::<AnyIOPin>::None,
+            &uart::config::Config::default().baudrate(115_200.into()),
+        )?,
+        identity,
+        store,
+        settings,
+        orientation,
+        profile,
+        badge: badge.clone(),
+        broadcaster: broadcaster.clone(),
+        esp_now: esp_now.clone(),
+        display: tx.clone(),
+        start,
+    };
+    std::thread::Builder::new()
+        .stack_size(8196)
+        .spawn(move || shell.run().unwrap())?;
 
     set_thread_spawn_configuration("send-thread\0", 8196, 15, None)?;
     let espnow_recv = esp_now.clone();
@@ -287,27 +433,67 @@ fn main() -> Result<()> {
 
             let mut led = PinDriver::output(led).unwrap();
             led.set_high().unwrap();
+            let mut last_status: Option<Instant> = None;
             loop {
-                std::thread::sleep(std::time::Duration::from_secs(
-                    rng.gen_range(SEND_DELAY_RANGE),
-                ));
+                let next = Instant::now() + broadcaster.lock().unwrap().delay(rng);
 
-                let poem_id = rng.gen_range(0..poems_len) as u8;
-                let device_id: u8 = DEVICE_ID.parse().unwrap();
+                // Relay poems until it's time for our own broadcast
+                while let Ok(message) =
+                    relay_rx.recv_timeout(next.saturating_duration_since(Instant::now()))
+                {
+                    std::thread::sleep(Duration::from_millis(rng.gen_range(RELAY_DELAY_RANGE)));
+                    let packet = broadcaster.lock().unwrap().message(message);
+                    if let Err(e) = espnow::broadcast(&espnow_recv, &[packet.serialize()]) {
+                        log::warn!("Relaying {:?} failed: {}", packet.message, e);
+                        continue;
+                    }
+                    log::info!("Relayed {:?}", packet.message);
+                    badge.counters.lock().unwrap().relayed += 1;
+                }
+
+                // Let the others know how we're doing
+                if last_status.map_or(true, |t| t.elapsed() >= STATUS_INTERVAL) {
+                    let battery = badge.battery.lock().unwrap().map(|level| level.percent);
+                    let packet = broadcaster.lock().unwrap().status(battery);
+                    match espnow::broadcast(&espnow_recv, &[packet.serialize()]) {
+                        Ok(()) => {
+                            log::info!("Broadcast {:?}", packet.message);
+                            last_status = Some(Instant::now());
+                        }
+                        // Try again after the next poem
+                        Err(e) => log::warn!("Broadcasting {:?} failed: {}", packet.message, e),
+                    }
+                }
 
-                let payload: [u8; 2] = [poem_id, device_id];
-                espnow_recv.send(BROADCAST, &payload).unwrap();
+                let library = badge.library.lock().unwrap();
+                let packets = broadcaster.lock().unwrap().next_packets(rng, &library);
+                drop(library);
+                let Some(packets) = packets else {
+                    log::warn!("The poem library is empty");
+                    continue;
+                };
+                // The radio can run out of buffers, the next broadcast is
+                // another chance
+                let frames: Vec<Vec<u8>> = packets.iter().map(Packet::serialize).collect();
+                if let Err(e) = espnow::broadcast(&espnow_recv, &frames) {
+                    log::warn!("Broadcasting {:?} failed: {}", packets[0].message, e);
+                    continue;
+                }
 
-                log::info!("Broadcast poem {} from {}", poem_id, DEVICE_ID);
-                let mut s = sent.lock().unwrap();
-                *s += 1;
+                log::info!(
+                    "Broadcast {:?} from {} in {} frames",
+                    packets[0].message,
+                    device_id,
+                    packets.len()
+                );
+                badge.counters.lock().unwrap().sent += 1;
 
                 // Blink gpio 21 three times
                 for _ in 0..3 {
                     led.set_low().unwrap();
-                    std::thread::sleep(std::time::Duration::from_millis(200));
+                    std::thread::sleep(Duration::from_millis(200));
                     led.set_high().unwrap();
-                    std::thread::sleep(std::time::Duration::from_millis(200));
+                    std::thread::sleep(Duration::from_millis(200));
                 }
             }
         })?;
@@ -316,12 +502,3 @@ fn main() -> Result<()> {
     display_thread.join().unwrap();
     Ok(())
 }
-
-fn display_poem(poem: Poem, display: &mut Display, poems: &[String], intro_text: &str) {
-    log::info!("Displaying poem id: {}, from {}", poem.id, poem.src);
-    display.clear(BinaryColor::Off).unwrap();
-    let text = &poems[poem.id as usize];
-    let s = format!("{}{}", intro_text, text);
-    effects::type_text(display, &s).unwrap();
-    std::thread::sleep(std::time::Duration::from_secs(2));
-}
@@ -0,0 +1,40 @@
+use std::ffi::c_void;
+
+use anyhow::Result;
+use esp_idf_hal::sys::esp;
+use vogon::power::PowerMode;
+
+/// Set the CPU clock and whether the chip sleeps while all threads wait.
+///
+/// Needs power management and tickless idle, see `sdkconfig.defaults`.
+pub fn configure_cpu(mode: &PowerMode) -> Result<()> {
+    let config = esp_idf_sys::esp_pm_config_t {
+        max_freq_mhz: mode.max_cpu_mhz as i32,
+        min_freq_mhz: mode.min_cpu_mhz as i32,
+        light_sleep_enable: mode.light_sleep,
+    };
+    esp!(unsafe { esp_idf_sys::esp_pm_configure(&config as *const _ as *const c_void) })?;
+    Ok(())
+}
+
+/// Let the radio sleep between wake windows, or keep it listening. Call it
+/// after Wi-Fi was started.
+pub fn configure_radio(mode: &PowerMode) -> Result<()> {
+    match mode.radio {
+        None => {
+            esp!(unsafe { esp_idf_sys::esp_wifi_set_ps(esp_idf_sys::wifi_ps_type_t_WIFI_PS_NONE) })?
+        }
+        Some(duty) => {
+            esp!(unsafe {
+                esp_idf_sys::esp_wifi_set_ps(esp_idf_sys::wifi_ps_type_t_WIFI_PS_MIN_MODEM)
+            })?;
+            esp!(unsafe { esp_idf_sys::esp_now_set_wake_window(duty.window.as_millis() as u16) })?;
+            esp!(unsafe {
+                esp_idf_sys::esp_wifi_connectionless_module_set_wake_interval(
+                    duty.interval.as_millis() as u16,
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use vogon::power::Profile;
+use vogon::screen::{Orientation, Rotation};
+
+const NAMESPACE: &str = "settings";
+const KEY_ROTATION: &str = "rotation";
+const KEY_MIRRORED: &str = "mirrored";
+const KEY_POWER: &str = "power";
+
+/// Settings that can be changed on the console, persisted in NVS so they
+/// survive a reboot.
+pub struct SettingsStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl SettingsStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+        Ok(SettingsStore {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    /// How the display is mounted, upright if it was never set.
+    pub fn orientation(&self) -> Result<Orientation> {
+        let rotation = match self.nvs.get_u16(KEY_ROTATION)? {
+            Some(degrees) => Rotation::from_degrees(degrees).unwrap_or_else(|| {
+                log::warn!("Ignoring stored rotation: {}", degrees);
+                Rotation::default()
+            }),
+            None => Rotation::default(),
+        };
+        Ok(Orientation {
+            rotation,
+            mirrored: self.nvs.get_u8(KEY_MIRRORED)? == Some(1),
+        })
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) -> Result<()> {
+        self.nvs
+            .set_u16(KEY_ROTATION, orientation.rotation.degrees())?;
+        self.nvs.set_u8(KEY_MIRRORED, orientation.mirrored as u8)?;
+        Ok(())
+    }
+
+    /// The power profile, `default` if it was never set.
+    pub fn profile(&self, default: Profile) -> Result<Profile> {
+        let mut buf = [0u8; 16];
+        Ok(match self.nvs.get_str(KEY_POWER, &mut buf)? {
+            Some(profile) => profile.parse().unwrap_or_else(|e| {
+                log::warn!("Ignoring stored power profile: {}", e);
+                default
+            }),
+            None => default,
+        })
+    }
+
+    pub fn set_profile(&mut self, profile: Profile) -> Result<()> {
+        self.nvs.set_str(KEY_POWER, &profile.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Write;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::Result;
+use esp_idf_hal::delay::BLOCK;
+use esp_idf_hal::uart::UartDriver;
+use esp_idf_svc::espnow::EspNow;
+use vogon::corpus;
+use vogon::power::Profile;
+use vogon::protocol::Packet;
+use vogon::screen::Orientation;
+use vogon::shell::{self, Command, LineBuffer};
+use vogon::transceiver::{Badge, Broadcaster, Poem};
+use vogon::utils::mac_to_string;
+use vogon::TOTAL_DEVICES;
+
+use crate::espnow;
+use crate::identity::{Identity, IdentityStore};
+use crate::settings::SettingsStore;
+
+/// Line based command shell on the serial console.
+pub struct Shell {
+    pub uart: UartDriver<'static>,
+    pub identity: Identity,
+    pub store: IdentityStore,
+    pub settings: SettingsStore,
+    /// How the display is mounted, shared with the display thread.
+    pub orientation: Arc<Mutex<Orientation>>,
+    /// The power profile the badge booted with.
+    pub profile: Profile,
+    pub badge: Arc<Badge>,
+    pub broadcaster: Arc<Mutex<Broadcaster>>,
+    pub esp_now: Arc<EspNow<'static>>,
+    /// Poems sent here are shown on the display.
+    pub display: Sender<Poem>,
+    pub start: Instant,
+}
+
+impl Shell {
+    pub fn run(mut self) -> Result<()> {
+        let mut line = LineBuffer::default();
+        let mut buf = [0u8; 32];
+        self.write("\nType \"help\" for a list of commands\n> ")?;
+        loop {
+            let n = self.uart.read(&mut buf, BLOCK)?;
+            for byte in &buf[..n] {
+                if let Some(line) = line.push(*byte) {
+                    let out = match line.map(|line| shell::parse(&line)) {
+                        Ok(Ok(command)) => self.execute(command),
+                        Ok(Err(e)) => Ok(format!("Error: {}", e)),
+                        Err(e) => Ok(format!("Error: {}", e)),
+                    }
+                    .unwrap_or_else(|e| format!("Error: {}", e));
+                    self.write(&format!("{}\n> ", out))?;
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, s: &str) -> Result<()> {
+        self.uart.write(s.replace('\n', "\r\n").as_bytes())?;
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command) -> Result<String> {
+        let mut out = String::new();
+        match command {
+            Command::Help => out.push_str(shell::HELP),
+            Command::Status => {
+                let c = self.badge.counters.lock().unwrap();
+                let now = self.start.elapsed();
+                write!(
+                    out,
+                    "Device {}/{} ({})\nReceived: {}, sent: {}, relayed: {}, duplicates: {}, dropped: {}\nNearby: {}, id collisions: {}\nPower profile: {}, battery: {}\nUptime: {}s",
+                    self.identity.id,
+                    TOTAL_DEVICES,
+                    self.identity.name,
+                    c.received,
+                    c.sent,
+                    c.relayed,
+                    c.duplicates,
+                    c.dropped,
+                    self.badge.peers.lock().unwrap().nearby(now),
+                    c.collisions,
+                    self.profile,
+                    match *self.badge.battery.lock().unwrap() {
+                        Some(level) => format!(
+                            "{}% ({}mV){}",
+                            level.percent,
+                            level.millivolts,
+                            if level.low { ", low" } else { "" }
+                        ),
+                        None => "not measured".to_string(),
+                    },
+                    now.as_secs()
+                )?;
+            }
+            Command::Send(poem_id) => {
+                self.check_poem_id(poem_id)?;
+                // Poems that were added to this badge go out as text
+                let library = self.badge.library.lock().unwrap();
+                let packets = self
+                    .broadcaster
+                    .lock()
+                    .unwrap()
+                    .packets(poem_id, &library)
+                    .ok_or_else(|| anyhow::anyhow!("Poem {} is too long to send", poem_id))?;
+                drop(library);
+                let frames: Vec<Vec<u8>> = packets.iter().map(Packet::serialize).collect();
+                espnow::broadcast(&self.esp_now, &frames)?;
+                self.badge.counters.lock().unwrap().sent += 1;
+                write!(out, "Broadcast poem {}", poem_id)?;
+            }
+            Command::Share(poem_id) => {
+                let text = self.check_poem_id(poem_id)?;
+                let packets = self
+                    .broadcaster
+                    .lock()
+                    .unwrap()
+                    .text_packets(&text)
+                    .ok_or_else(|| anyhow::anyhow!("Poem {} is too long to share", poem_id))?;
+                let frames: Vec<Vec<u8>> = packets.iter().map(Packet::serialize).collect();
+                espnow::broadcast(&self.esp_now, &frames)?;
+                self.badge.counters.lock().unwrap().sent += 1;
+                write!(out, "Shared poem {} in {} frames", poem_id, packets.len())?;
+            }
+            Command::Show(poem_id) => {
+                self.check_poem_id(poem_id)?;
+                self.display
+                    .send(Poem::local(poem_id, self.identity.id.get()))?;
+                write!(out, "Showing poem {}", poem_id)?;
+            }
+            Command::List => {
+                for (id, poem) in self.badge.library.lock().unwrap().iter() {
+                    match corpus::get(id).filter(|entry| entry.body == poem) {
+                        Some(entry) => writeln!(
+                            out,
+                            "{:>3}: {} ({})",
+                            id,
+                            entry.title,
+                            entry.credit().unwrap_or("unknown")
+                        )?,
+                        None => writeln!(out, "{:>3}: {}", id, poem.lines().next().unwrap_or(""))?,
+                    }
+                }
+            }
+            Command::Add(text) => {
+                let id = self.badge.library.lock().unwrap().add(text)?;
+                write!(out, "Added poem {}", id)?;
+            }
+            Command::Remove(poem_id) => {
+                self.badge.library.lock().unwrap().remove(poem_id)?;
+                write!(out, "Removed poem {}", poem_id)?;
+            }
+            Command::Peers => {
+                let now = self.start.elapsed();
+                let mut peers = self.badge.peers.lock().unwrap();
+                peers.expire(now);
+                if peers.is_empty() {
+                    out.push_str("No peers heard recently");
+                }
+                for ((mac, device_id), peer) in peers.iter() {
+                    writeln!(
+                        out,
+                        "{} device {:>2}, {} packets, {} poems, rssi {}, battery {}, first seen {}s ago, last seen {}s ago",
+                        mac_to_string(mac),
+                        device_id,
+                        peer.packets,
+                        peer.poems,
+                        match peer.rssi_avg {
+                            Some(rssi) => format!("{:.0}dBm", rssi),
+                            None => "-".to_string(),
+                        },
+                        match peer.battery {
+                            Some(percent) => format!("{}%", percent),
+                            None => "-".to_string(),
+                        },
+                        now.saturating_sub(peer.first_seen).as_secs(),
+                        now.saturating_sub(peer.last_seen).as_secs()
+                    )?;
+                }
+            }
+            Command::SetId(id) => {
+                self.store.set_id(id)?;
+                write!(out, "Device id set to {}, reboot to apply", id)?;
+            }
+            Command::SetName(name) => {
+                self.store.set_name(&name)?;
+                write!(out, "Name set to {}", name)?;
+                self.identity.name = name;
+            }
+            Command::SetRotation(rotation) => {
+                self.set_orientation(|orientation| orientation.rotation = rotation)?;
+                write!(out, "Rotation set to {} degrees", rotation.degrees())?;
+            }
+            Command::SetMirror(mirrored) => {
+                self.set_orientation(|orientation| orientation.mirrored = mirrored)?;
+                write!(out, "Mirror {}", if mirrored { "on" } else { "off" })?;
+            }
+            Command::SetPower(profile) => {
+                self.settings.set_profile(profile)?;
+                write!(out, "Power profile set to {}, reboot to apply", profile)?;
+            }
+            Command::Reboot => {
+                self.write("Rebooting..\n")?;
+                unsafe { esp_idf_sys::esp_restart() };
+            }
+        }
+        Ok(out.trim_end().to_string())
+    }
+
+    /// Change how the display is mounted, right away and after a reboot.
+    fn set_orientation(&mut self, change: impl FnOnce(&mut Orientation)) -> Result<()> {
+        let mut orientation = self.orientation.lock().unwrap();
+        change(&mut orientation);
+        self.settings.set_orientation(*orientation)
+    }
+
+    /// The text of a poem, or an error if it isn't in the library.
+    fn check_poem_id(&self, poem_id: u8) -> Result<String> {
+        self.badge
+            .library
+            .lock()
+            .unwrap()
+            .get(poem_id)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("No poem {}, try \"list\"", poem_id))
+    }
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use esp_idf_hal::sys::esp;
+
+/// Where the poem library is mounted.
+pub const POEMS_PATH: &str = "/poems";
+
+const BASE_PATH: &[u8] = b"/poems\0";
+const PARTITION_LABEL: &[u8] = b"storage\0";
+const MAX_FILES: usize = 5;
+
+/// Mount the `storage` SPIFFS partition on `POEMS_PATH`, formatting it if it
+/// was never used, so it can be accessed through `std::fs`.
+pub fn mount() -> Result<()> {
+    let conf = esp_idf_sys::esp_vfs_spiffs_conf_t {
+        base_path: BASE_PATH.as_ptr() as _,
+        partition_label: PARTITION_LABEL.as_ptr() as _,
+        max_files: MAX_FILES as _,
+        format_if_mount_failed: true,
+    };
+    esp!(unsafe { esp_idf_sys::esp_vfs_spiffs_register(&conf) })?;
+
+    let (mut total, mut used) = (0, 0);
+    esp!(unsafe {
+        esp_idf_sys::esp_spiffs_info(PARTITION_LABEL.as_ptr() as _, &mut total, &mut used)
+    })?;
+    log::info!("Mounted {}, {}/{} bytes used", POEMS_PATH, used, total);
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Turns `assets/poems.txt` into the static table in `corpus`, and the PNGs
+//! in `assets/` into the bitmaps in `assets`.
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+#[path = "src/assets/convert.rs"]
+#[allow(dead_code)]
+mod convert;
+#[path = "src/font.rs"]
+#[allow(dead_code)]
+mod font;
+#[path = "src/corpus/format.rs"]
+#[allow(dead_code)]
+mod format;
+#[path = "src/glyphs.rs"]
+#[allow(dead_code)]
+mod glyphs;
+#[path = "src/layout.rs"]
+#[allow(dead_code)]
+mod layout;
+#[path = "src/assets/png.rs"]
+#[allow(dead_code)]
+mod png;
+#[path = "src/corpus/validate.rs"]
+mod validate;
+
+/// Size of the smallest panel, for builds that don't set `PANEL`.
+const DEFAULT_SCREEN: (u32, u32) = (128, 32);
+
+fn main() {
+    let assets = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("../assets");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let (width, height) = screen_size();
+    fs::write(out_dir.join("poems.rs"), poems(&assets, width)).unwrap();
+    fs::write(out_dir.join("images.rs"), images(&assets, width, height)).unwrap();
+}
+
+/// Width and height in pixels of the panel the firmware is built for, from
+/// `PANEL` in `.cargo/config.toml`, like "ssd1306-128x64". The panel's own
+/// orientation is used: poems on a panel that is rotated to portrait at
+/// runtime wrap to more lines than checked here.
+fn screen_size() -> (u32, u32) {
+    println!("cargo:rerun-if-env-changed=PANEL");
+    let panel = env::var("PANEL").unwrap_or_default();
+    panel
+        .split('-')
+        .find_map(|part| {
+            let (width, height) = part.split_once('x')?;
+            Some((width.parse().ok()?, height.parse().ok()?))
+        })
+        .unwrap_or(DEFAULT_SCREEN)
+}
+
+/// Poems are checked against a screen `width` pixels wide.
+fn poems(assets: &Path, width: u32) -> String {
+    let path = assets.join("poems.txt");
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let text = fs::read_to_string(&path).unwrap();
+    let mut poems = match format::parse(&text) {
+        Ok(poems) => poems,
+        Err(e) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let problems = validate::validate(&poems, width);
+    if !problems.is_empty() {
+        eprintln!("error: {} has {} problems:", path.display(), problems.len());
+        for problem in problems {
+            eprintln!("  {}", problem);
+        }
+        std::process::exit(1);
+    }
+    poems.sort_by_key(|poem| poem.id);
+
+    let mut out = "&[\n".to_string();
+    for poem in poems {
+        writeln!(
+            out,
+            "    Entry {{ id: {}, title: {:?}, author: {:?}, source: {:?}, tags: &{:?}, body: {:?} }},",
+            poem.id, poem.title, poem.author, poem.source, poem.tags, poem.body
+        )
+        .unwrap();
+    }
+    out.push(']');
+    out
+}
+
+/// A `Bitmap` constant for every PNG, converted with the options for it in
+/// `images.txt`. Images larger than `width` by `height` are cropped or
+/// scaled.
+fn images(assets: &Path, width: u32, height: u32) -> String {
+    // New PNGs are picked up too
+    println!("cargo:rerun-if-changed={}", assets.display());
+    let manifest = assets.join("images.txt");
+    println!("cargo:rerun-if-changed={}", manifest.display());
+
+    let mut options = std::collections::BTreeMap::new();
+    let text = fs::read_to_string(&manifest).unwrap_or_default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (file, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match convert::Options::parse(rest) {
+            Ok(parsed) => options.insert(file.to_string(), parsed),
+            Err(e) => fail(&format!("{}:{}: {}", manifest.display(), i + 1, e)),
+        };
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(assets)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+        })
+        .collect();
+    files.sort();
+
+    let mut out = format!(
+        "/// Width and height of the panel the images were converted for.\n\
+         pub const SCREEN_SIZE: (u32, u32) = ({}, {});\n",
+        width, height
+    );
+    for path in files {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let file = path.file_name().unwrap().to_str().unwrap();
+        let image = match png::decode(&fs::read(&path).unwrap()) {
+            Ok(image) => image,
+            Err(e) => fail(&format!("{}: {}", path.display(), e)),
+        };
+        let default = convert::Options::default();
+        let bits = convert::convert(&image, options.get(file).unwrap_or(&default), width, height);
+        let name: String = path
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "/// {}x{} pixels, from `{}`.\npub const {}: Bitmap = Bitmap {{ width: {}, height: {}, data: &{:?} }};",
+            bits.width, bits.height, file, name, bits.width, bits.height, bits.data
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
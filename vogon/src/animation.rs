@@ -0,0 +1,249 @@
+//! Frame based animations.
+//!
+//! An `Animation` draws a single frame every time it is stepped instead of
+//! sleeping between frames itself, so whoever drives it can stop it after any
+//! frame. `Sequence` and `Parallel` combine animations, and a `Player` runs
+//! them at a fixed frame rate.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::screen::Screen;
+
+/// Frame rate of a `Player` by default.
+pub const DEFAULT_FPS: u32 = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Done,
+}
+
+pub trait Animation<S: Screen> {
+    /// Draw the frame `dt` after the previous one. The player flushes the
+    /// display afterwards.
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status;
+}
+
+/// An animation of any type, so different ones can be combined.
+pub type BoxedAnimation<'a, S> = Box<dyn Animation<S> + 'a>;
+
+/// Animations that run one after the other.
+pub struct Sequence<'a, S> {
+    animations: VecDeque<BoxedAnimation<'a, S>>,
+}
+
+impl<'a, S: Screen> Sequence<'a, S> {
+    pub fn new() -> Self {
+        Sequence {
+            animations: VecDeque::new(),
+        }
+    }
+
+    pub fn then(mut self, animation: impl Animation<S> + 'a) -> Self {
+        self.animations.push_back(Box::new(animation));
+        self
+    }
+}
+
+impl<S: Screen> Default for Sequence<'_, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Screen> Animation<S> for Sequence<'_, S> {
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status {
+        let Some(current) = self.animations.front_mut() else {
+            return Status::Done;
+        };
+        if current.frame(display, dt) == Status::Done {
+            self.animations.pop_front();
+        }
+        if self.animations.is_empty() {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+}
+
+/// Animations that run at the same time, until all of them are done. They
+/// are drawn in the order they were added.
+pub struct Parallel<'a, S> {
+    tracks: Vec<(BoxedAnimation<'a, S>, Status)>,
+}
+
+impl<'a, S: Screen> Parallel<'a, S> {
+    pub fn new() -> Self {
+        Parallel { tracks: vec![] }
+    }
+
+    pub fn with(mut self, animation: impl Animation<S> + 'a) -> Self {
+        self.tracks.push((Box::new(animation), Status::Running));
+        self
+    }
+}
+
+impl<S: Screen> Default for Parallel<'_, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Screen> Animation<S> for Parallel<'_, S> {
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status {
+        for (animation, status) in &mut self.tracks {
+            if *status == Status::Running {
+                *status = animation.frame(display, dt);
+            }
+        }
+        if self
+            .tracks
+            .iter()
+            .all(|(_, status)| *status == Status::Done)
+        {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+}
+
+/// Keeps what is on the screen for a while.
+pub struct Pause {
+    left: Duration,
+}
+
+impl Pause {
+    pub fn new(duration: Duration) -> Self {
+        Pause { left: duration }
+    }
+}
+
+impl<S: Screen> Animation<S> for Pause {
+    fn frame(&mut self, _display: &mut S, dt: Duration) -> Status {
+        self.left = self.left.saturating_sub(dt);
+        if self.left.is_zero() {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+}
+
+/// The render loop: steps an animation at a fixed frame rate.
+pub struct Player {
+    frame: Duration,
+}
+
+impl Player {
+    pub fn new(fps: u32) -> Self {
+        Player {
+            frame: Duration::from_secs(1) / fps,
+        }
+    }
+
+    /// Play `animation` until it's done or `cancelled` returns true, which is
+    /// checked before every frame. Returns whether the animation finished.
+    pub fn play<S: Screen>(
+        &self,
+        display: &mut S,
+        animation: &mut dyn Animation<S>,
+        mut cancelled: impl FnMut() -> bool,
+    ) -> Result<bool> {
+        loop {
+            if cancelled() {
+                return Ok(false);
+            }
+            let status = animation.frame(display, self.frame);
+            display.flush()?;
+            if status == Status::Done {
+                return Ok(true);
+            }
+            display.delay(self.frame);
+        }
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Player::new(DEFAULT_FPS)
+    }
+}
+
+/// Play an animation to the end at the default frame rate.
+pub fn play<S: Screen>(display: &mut S, mut animation: impl Animation<S>) -> Result<()> {
+    Player::default().play(display, &mut animation, || false)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::HeadlessScreen;
+
+    /// Counts its frames.
+    struct Frames<'a> {
+        frames: &'a mut u32,
+        total: u32,
+    }
+
+    impl<S: Screen> Animation<S> for Frames<'_> {
+        fn frame(&mut self, _display: &mut S, _dt: Duration) -> Status {
+            *self.frames += 1;
+            if *self.frames == self.total {
+                Status::Done
+            } else {
+                Status::Running
+            }
+        }
+    }
+
+    #[test]
+    fn sequence_and_parallel() {
+        let (mut a, mut b, mut c) = (0, 0, 0);
+        let mut display = HeadlessScreen::default();
+        let animation = Sequence::new()
+            .then(Frames {
+                frames: &mut a,
+                total: 3,
+            })
+            .then(
+                Parallel::new()
+                    .with(Frames {
+                        frames: &mut b,
+                        total: 2,
+                    })
+                    .with(Pause::new(Duration::from_millis(200))),
+            )
+            .then(Frames {
+                frames: &mut c,
+                total: 1,
+            });
+        play(&mut display, animation).unwrap();
+        assert_eq!((a, b, c), (3, 2, 1));
+        // 3 + 5 + 1 frames at 40ms, without waiting after the last one
+        assert_eq!(display.elapsed, Duration::from_millis(8 * 40));
+    }
+
+    #[test]
+    fn cancel() {
+        let mut frames = 0;
+        let mut display = HeadlessScreen::default();
+        let mut animation = Frames {
+            frames: &mut frames,
+            total: 100,
+        };
+        let mut checks = 0;
+        let finished = Player::default()
+            .play(&mut display, &mut animation, || {
+                checks += 1;
+                checks > 10
+            })
+            .unwrap();
+        assert!(!finished);
+        assert_eq!(frames, 10);
+    }
+}
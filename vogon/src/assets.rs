@@ -0,0 +1,47 @@
+//! Assets shared by the firmware and the simulator.
+//!
+//! The images are generated from the PNGs in `assets/` by the build script,
+//! see `assets/images.txt`.
+use embedded_graphics::image::ImageRaw;
+use embedded_graphics::pixelcolor::BinaryColor;
+
+pub mod convert;
+pub mod png;
+
+pub const ASCII_CHEWIE: &[u8; 2806] = include_bytes!("../../assets/chewie.txt");
+
+/// A 1bpp image, rows padded to whole bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: &'static [u8],
+}
+
+impl Bitmap {
+    pub fn raw(&self) -> ImageRaw<'static, BinaryColor> {
+        ImageRaw::new(self.data, self.width)
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/images.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::prelude::*;
+
+    #[test]
+    fn images() {
+        for image in [LOGOTYPE, RUST, LOGO_1BPP] {
+            assert!(image.width <= SCREEN_SIZE.0 && image.height <= SCREEN_SIZE.1);
+        }
+        if SCREEN_SIZE != (128, 32) {
+            return;
+        }
+        assert_eq!(LOGOTYPE.raw().size(), Size::new(128, 11));
+        assert_eq!(RUST.raw().size(), Size::new(128, 32));
+        // Scaled down to fit the screen
+        assert_eq!((LOGO_1BPP.width, LOGO_1BPP.height), (21, 32));
+    }
+}
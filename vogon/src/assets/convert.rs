@@ -0,0 +1,258 @@
+//! Turns PNG images into 1bpp bitmaps for the screen.
+//!
+//! This module is also compiled into the build script, that converts every
+//! PNG in `assets/`.
+use std::fmt;
+
+use super::png::Png;
+
+/// How an image is converted, from a line in `assets/images.txt` like
+/// `logo.png fit dither`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// Pixels at least this bright light up.
+    pub threshold: u8,
+    /// Use error diffusion instead of a hard threshold, for photos and
+    /// gradients.
+    pub dither: bool,
+    /// Light up the dark pixels instead, for black on white drawings.
+    pub invert: bool,
+    /// Scale the image down to fit the screen, instead of cropping it.
+    pub fit: bool,
+    /// Top left corner of the part of the image that is kept when it is
+    /// larger than the screen. The middle part is kept if not set.
+    pub crop: Option<(u32, u32)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            threshold: 128,
+            dither: false,
+            invert: false,
+            fit: false,
+            crop: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidOption(pub String);
+
+impl fmt::Display for InvalidOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid option {:?}, expected threshold=<0..255>, dither, invert, fit or crop=<x>,<y>",
+            self.0
+        )
+    }
+}
+
+impl Options {
+    /// Parse options separated by whitespace.
+    pub fn parse(s: &str) -> Result<Self, InvalidOption> {
+        let mut options = Options::default();
+        for option in s.split_whitespace() {
+            let invalid = || InvalidOption(option.to_string());
+            match option.split_once('=') {
+                None if option == "dither" => options.dither = true,
+                None if option == "invert" => options.invert = true,
+                None if option == "fit" => options.fit = true,
+                Some(("threshold", value)) => {
+                    options.threshold = value.parse().map_err(|_| invalid())?
+                }
+                Some(("crop", value)) => {
+                    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+                    options.crop = Some((
+                        x.parse().map_err(|_| invalid())?,
+                        y.parse().map_err(|_| invalid())?,
+                    ));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A 1bpp image, rows padded to whole bytes with the most significant bit on
+/// the left, like `ImageRaw` expects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bits {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Bits {
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        let stride = self.width.div_ceil(8);
+        self.data[(y * stride + x / 8) as usize] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// Convert `png` to an image of at most `max_width` by `max_height` pixels.
+/// Transparent parts are dark.
+pub fn convert(png: &Png, options: &Options, max_width: u32, max_height: u32) -> Bits {
+    let mut luma: Vec<f32> = png
+        .pixels
+        .iter()
+        .map(|(l, a)| *l as f32 * *a as f32 / 255.0)
+        .collect();
+    let (mut width, mut height) = (png.width, png.height);
+
+    if options.fit && (width > max_width || height > max_height) {
+        (luma, width, height) = scale(&luma, width, height, max_width, max_height);
+    }
+
+    // Crop
+    let (crop_width, crop_height) = (width.min(max_width), height.min(max_height));
+    let (x0, y0) = options
+        .crop
+        .unwrap_or(((width - crop_width) / 2, (height - crop_height) / 2));
+    let (x0, y0) = (x0.min(width - crop_width), y0.min(height - crop_height));
+    let mut cropped = Vec::with_capacity((crop_width * crop_height) as usize);
+    for y in y0..y0 + crop_height {
+        for x in x0..x0 + crop_width {
+            let l = luma[(y * width + x) as usize];
+            cropped.push(if options.invert { 255.0 - l } else { l });
+        }
+    }
+    let (width, height) = (crop_width, crop_height);
+
+    let stride = width.div_ceil(8);
+    let mut data = vec![0; (stride * height) as usize];
+    let threshold = options.threshold as f32;
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            let on = cropped[i] >= threshold;
+            if on {
+                data[(y * stride + x / 8) as usize] |= 0x80 >> (x % 8);
+            }
+            if options.dither {
+                // Floyd-Steinberg: pass the error on to the pixels that are
+                // still to come
+                let error = cropped[i] - if on { 255.0 } else { 0.0 };
+                let mut spread = |dx: i32, dy: u32, weight: f32| {
+                    let (x, y) = (x as i32 + dx, y + dy);
+                    if x >= 0 && (x as u32) < width && y < height {
+                        cropped[(y * width + x as u32) as usize] += error * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+    }
+    Bits {
+        width,
+        height,
+        data,
+    }
+}
+
+/// Scale down to fit `max_width` by `max_height`, keeping the aspect ratio,
+/// by averaging the pixels that end up in the same place.
+fn scale(
+    luma: &[f32],
+    width: u32,
+    height: u32,
+    max_width: u32,
+    max_height: u32,
+) -> (Vec<f32>, u32, u32) {
+    let factor = (width as f32 / max_width as f32).max(height as f32 / max_height as f32);
+    let new_width = ((width as f32 / factor).round() as u32).clamp(1, max_width);
+    let new_height = ((height as f32 / factor).round() as u32).clamp(1, max_height);
+    let mut sums = vec![(0.0, 0); (new_width * new_height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let nx = (x * new_width / width) as usize;
+            let ny = (y * new_height / height) as usize;
+            let sum = &mut sums[ny * new_width as usize + nx];
+            sum.0 += luma[(y * width + x) as usize];
+            sum.1 += 1;
+        }
+    }
+    let scaled = sums
+        .into_iter()
+        .map(|(sum, count)| sum / count.max(1) as f32)
+        .collect();
+    (scaled, new_width, new_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A horizontal gradient from black to white.
+    fn gradient(width: u32, height: u32) -> Png {
+        Png {
+            width,
+            height,
+            pixels: (0..width * height)
+                .map(|i| ((i % width * 255 / (width - 1)) as u8, 255))
+                .collect(),
+        }
+    }
+
+    fn lit(bits: &Bits) -> usize {
+        (0..bits.height)
+            .flat_map(|y| (0..bits.width).map(move |x| (x, y)))
+            .filter(|(x, y)| bits.pixel(*x, *y))
+            .count()
+    }
+
+    #[test]
+    fn threshold_and_invert() {
+        let png = gradient(16, 2);
+        let bits = convert(&png, &Options::default(), 128, 32);
+        assert_eq!((bits.width, bits.height), (16, 2));
+        assert_eq!(bits.data, vec![0x00, 0xff, 0x00, 0xff]);
+
+        let options = Options::parse("invert threshold=64").unwrap();
+        let bits = convert(&png, &options, 128, 32);
+        assert_eq!(bits.data, vec![0xff, 0xf0, 0xff, 0xf0]);
+    }
+
+    #[test]
+    fn dither() {
+        let png = gradient(64, 32);
+        let bits = convert(&png, &Options::parse("dither").unwrap(), 128, 32);
+        // Half of a gradient is lit, spread out over the whole width
+        let lit = lit(&bits);
+        assert!(
+            (64 * 32 / 2 - 64..=64 * 32 / 2 + 64).contains(&lit),
+            "{}",
+            lit
+        );
+        assert!((0..32).any(|y| bits.pixel(20, y)));
+        assert!((0..32).any(|y| !bits.pixel(44, y)));
+    }
+
+    #[test]
+    fn crop_and_fit() {
+        let png = gradient(256, 64);
+        let bits = convert(&png, &Options::default(), 128, 32);
+        assert_eq!((bits.width, bits.height), (128, 32));
+        // The middle of the gradient was kept
+        assert!(!bits.pixel(0, 0) && bits.pixel(127, 0));
+
+        let bits = convert(&png, &Options::parse("crop=0,0").unwrap(), 128, 32);
+        assert_eq!(lit(&bits), 0);
+
+        let bits = convert(&png, &Options::parse("fit").unwrap(), 128, 32);
+        assert_eq!((bits.width, bits.height), (128, 32));
+        assert_eq!(lit(&bits), 64 * 32);
+
+        let png = gradient(50, 600);
+        let bits = convert(&png, &Options::parse("fit").unwrap(), 128, 32);
+        assert_eq!((bits.width, bits.height), (3, 32));
+
+        assert!(Options::parse("crop=1").is_err());
+        assert!(Options::parse("sharpen").is_err());
+    }
+}
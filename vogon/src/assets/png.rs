@@ -0,0 +1,460 @@
+//! Just enough of a PNG decoder for the images in `assets/`, so the build
+//! script doesn't need any dependencies.
+//!
+//! Supports every color type and bit depth, but not interlacing. Checksums
+//! aren't verified.
+use std::fmt;
+
+/// A decoded image in grayscale.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Png {
+    pub width: u32,
+    pub height: u32,
+    /// Luma and alpha of every pixel, row by row.
+    pub pixels: Vec<(u8, u8)>,
+}
+
+impl Png {
+    pub fn pixel(&self, x: u32, y: u32) -> (u8, u8) {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError(pub String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid PNG: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn error<T>(message: &str) -> Result<T, DecodeError> {
+    Err(DecodeError(message.to_string()))
+}
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub fn decode(data: &[u8]) -> Result<Png, DecodeError> {
+    let Some(mut rest) = data.strip_prefix(SIGNATURE) else {
+        return error("no PNG signature");
+    };
+    let mut header = None;
+    let mut palette: Vec<(u8, u8, u8)> = vec![];
+    let mut transparency: Vec<u8> = vec![];
+    let mut compressed = vec![];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        let Some(chunk) = rest.get(8..8 + length) else {
+            return error("truncated chunk");
+        };
+        match kind {
+            b"IHDR" if length == 13 => header = Some(chunk.to_vec()),
+            b"PLTE" => palette = chunk.chunks(3).map(|c| (c[0], c[1], c[2])).collect(),
+            b"tRNS" => transparency = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = &rest[12 + length..];
+    }
+    let Some(header) = header else {
+        return error("no header");
+    };
+
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let (depth, color_type) = (header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return error("interlaced images aren't supported");
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => return error("unknown color type"),
+    };
+    if ![1, 2, 4, 8, 16].contains(&depth) {
+        return error("unknown bit depth");
+    }
+
+    let data = inflate_zlib(&compressed)?;
+    let stride = (width as usize * channels * depth).div_ceil(8);
+    let bpp = (channels * depth / 8).max(1);
+    let rows = unfilter(&data, stride, bpp, height as usize)?;
+
+    let max = (1u32 << depth) - 1;
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for row in rows {
+        // Samples scaled to 0..=255, or palette indices
+        let sample = |i: usize| -> u32 {
+            match depth {
+                16 => row[i * 2] as u32,
+                8 => row[i] as u32,
+                _ => {
+                    let bit = i * depth;
+                    (row[bit / 8] as u32 >> (8 - depth - bit % 8)) & max
+                }
+            }
+        };
+        let scale = |v: u32| {
+            if depth == 16 {
+                v as u8
+            } else {
+                (v * 255 / max) as u8
+            }
+        };
+        for x in 0..width as usize {
+            let i = x * channels;
+            let pixel = match color_type {
+                0 => (scale(sample(i)), 255),
+                4 => (scale(sample(i)), scale(sample(i + 1))),
+                2 => (
+                    luma(scale(sample(i)), scale(sample(i + 1)), scale(sample(i + 2))),
+                    255,
+                ),
+                6 => (
+                    luma(scale(sample(i)), scale(sample(i + 1)), scale(sample(i + 2))),
+                    scale(sample(i + 3)),
+                ),
+                _ => {
+                    let index = sample(i) as usize;
+                    let Some((r, g, b)) = palette.get(index) else {
+                        return error("palette index out of range");
+                    };
+                    (
+                        luma(*r, *g, *b),
+                        transparency.get(index).copied().unwrap_or(255),
+                    )
+                }
+            };
+            pixels.push(pixel);
+        }
+    }
+    Ok(Png {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undo the filter of every row.
+fn unfilter(
+    data: &[u8],
+    stride: usize,
+    bpp: usize,
+    height: usize,
+) -> Result<Vec<Vec<u8>>, DecodeError> {
+    if data.len() < (stride + 1) * height {
+        return error("not enough image data");
+    }
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    let empty = vec![0; stride];
+    for y in 0..height {
+        let line = &data[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let prior = rows.last().unwrap_or(&empty);
+        let mut row = line[1..].to_vec();
+        for i in 0..stride {
+            let a = if i >= bpp { row[i - bpp] } else { 0 };
+            let (b, c) = (prior[i], if i >= bpp { prior[i - bpp] } else { 0 });
+            row[i] = row[i].wrapping_add(match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return error("unknown filter"),
+            });
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Reads a deflate stream bit by bit, least significant bit first.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: usize) -> Result<u32, DecodeError> {
+        let mut value = 0;
+        for i in 0..n {
+            let Some(byte) = self.data.get(self.position / 8) else {
+                return error("truncated deflate stream");
+            };
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+/// A canonical Huffman code, by the number of codes of every length.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, DecodeError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        error("invalid Huffman code")
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of the code length code lengths in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompress a zlib stream, as described in RFC 1950 and 1951.
+pub fn inflate_zlib(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < 2 || data[0] & 0x0f != 8 {
+        return error("not a deflate stream");
+    }
+    let bits = &mut Bits {
+        data: &data[2..],
+        position: 0,
+    };
+    let mut out = vec![];
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let start = bits.position / 8;
+                let Some(header) = bits.data.get(start..start + 4) else {
+                    return error("truncated stored block");
+                };
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let Some(stored) = bits.data.get(start + 4..start + 4 + length) else {
+                    return error("truncated stored block");
+                };
+                out.extend_from_slice(stored);
+                bits.position = (start + 4 + length) * 8;
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(bits, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                inflate_block(bits, &literals, &distances, &mut out)?;
+            }
+            _ => return error("invalid block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), DecodeError> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for i in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*i] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + bits.bits(2)?),
+                None => return error("repeat without a length"),
+            },
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return error("too many code lengths");
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+) -> Result<(), DecodeError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return error("invalid length");
+                }
+                let length =
+                    LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as usize)? as usize;
+                let d = distances.decode(bits)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return error("invalid distance");
+                }
+                let distance =
+                    DISTANCE_BASE[d] as usize + bits.bits(DISTANCE_EXTRA[d] as usize)? as usize;
+                if distance > out.len() {
+                    return error("distance too far back");
+                }
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG with the image data in a stored deflate block.
+    fn png(width: u32, height: u32, depth: u8, color_type: u8, data: &[u8]) -> Vec<u8> {
+        let chunk = |kind: &[u8], body: &[u8]| {
+            let mut chunk = (body.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(kind);
+            chunk.extend_from_slice(body);
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(data);
+        zlib.extend_from_slice(&[0; 4]);
+
+        let mut png = SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &header));
+        png.extend(chunk(b"IDAT", &zlib));
+        png.extend(chunk(b"IEND", &[]));
+        png
+    }
+
+    #[test]
+    fn stored_and_filtered() {
+        // Two rows of 1 bit gray, and two rows of 8 bit gray with the "sub"
+        // and "up" filters
+        let image = decode(&png(3, 2, 1, 0, &[0, 0b1010_0000, 0, 0b0100_0000])).unwrap();
+        let luma: Vec<u8> = image.pixels.iter().map(|(l, _)| *l).collect();
+        assert_eq!(luma, vec![255, 0, 255, 0, 255, 0]);
+
+        let image = decode(&png(2, 2, 8, 0, &[1, 10, 5, 2, 1, 1])).unwrap();
+        let luma: Vec<u8> = image.pixels.iter().map(|(l, _)| *l).collect();
+        assert_eq!(luma, vec![10, 15, 11, 16]);
+
+        // RGBA
+        let image = decode(&png(1, 1, 8, 6, &[0, 255, 255, 255, 128])).unwrap();
+        assert_eq!(image.pixel(0, 0), (255, 128));
+
+        assert!(decode(b"GIF89a").is_err());
+        assert!(decode(&png(4, 4, 8, 0, &[0, 1])).is_err());
+    }
+
+    #[test]
+    fn compressed_assets() {
+        // Both were compressed with dynamic Huffman codes
+        let logotype = decode(include_bytes!("../../../assets/logotype.png")).unwrap();
+        assert_eq!((logotype.width, logotype.height), (128, 11));
+        assert!(logotype.pixels.iter().any(|(l, _)| *l == 0));
+        assert!(logotype.pixels.iter().any(|(l, _)| *l == 255));
+
+        let logo = decode(include_bytes!("../../../assets/logo_1bpp.png")).unwrap();
+        assert_eq!((logo.width, logo.height), (388, 600));
+    }
+}
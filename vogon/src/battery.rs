@@ -0,0 +1,189 @@
+//! Battery level, from the voltage of the LiPo.
+//!
+//! The battery is measured through a voltage divider on an ADC pin. Readings
+//! are noisy and sag while the radio transmits, so they are smoothed before
+//! they are turned into a charge level.
+use std::ops::Range;
+
+/// Battery voltage over the voltage at the pin, 2 for two equal resistors.
+pub const DEFAULT_DIVIDER: f32 = 2.0;
+/// Weight of a new reading in the moving average.
+pub const DEFAULT_SMOOTHING: f32 = 0.1;
+/// Charge in percent below which the badge saves power.
+pub const DEFAULT_LOW: u8 = 15;
+/// Seconds between broadcasts when the battery is low.
+pub const LOW_SEND_DELAY_RANGE: Range<u64> = 30..90;
+/// Percent the charge has to rise above the low threshold before the badge
+/// stops saving power, so it doesn't flip back and forth around it.
+const HYSTERESIS: u8 = 5;
+/// Resting voltage of a LiPo cell in mV by charge in percent. Between points
+/// the charge is interpolated.
+const DISCHARGE: [(u16, u8); 11] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3650, 20),
+    (3700, 30),
+    (3750, 40),
+    (3800, 50),
+    (3900, 65),
+    (4000, 80),
+    (4100, 90),
+    (4200, 100),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Battery {
+    pub divider: f32,
+    /// Weight of a new reading in the moving average, 1 to not smooth.
+    pub smoothing: f32,
+    /// Charge in percent below which the badge saves power.
+    pub low: u8,
+    /// Seconds between broadcasts when the battery is low.
+    pub low_send_delay: Range<u64>,
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Battery {
+            divider: DEFAULT_DIVIDER,
+            smoothing: DEFAULT_SMOOTHING,
+            low: DEFAULT_LOW,
+            low_send_delay: LOW_SEND_DELAY_RANGE,
+        }
+    }
+}
+
+impl Battery {
+    /// Seconds between broadcasts, `normal` unless the battery is low and
+    /// that is sooner than `low_send_delay`.
+    pub fn send_delay(&self, normal: &Range<u64>, low: bool) -> Range<u64> {
+        if low && self.low_send_delay.start > normal.start {
+            self.low_send_delay.clone()
+        } else {
+            normal.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub millivolts: u16,
+    pub percent: u8,
+    /// The badge should save power.
+    pub low: bool,
+}
+
+/// Charge in percent of a LiPo at `millivolts`.
+pub fn percent(millivolts: u16) -> u8 {
+    let (first, last) = (DISCHARGE[0], DISCHARGE[DISCHARGE.len() - 1]);
+    if millivolts <= first.0 {
+        return first.1;
+    }
+    if millivolts >= last.0 {
+        return last.1;
+    }
+    let i = DISCHARGE
+        .iter()
+        .position(|(mv, _)| *mv > millivolts)
+        .unwrap();
+    let ((mv0, p0), (mv1, p1)) = (DISCHARGE[i - 1], DISCHARGE[i]);
+    let t = (millivolts - mv0) as f32 / (mv1 - mv0) as f32;
+    (p0 as f32 + t * (p1 - p0) as f32).round() as u8
+}
+
+/// Smooths the readings of the ADC and decides when the battery is low.
+pub struct BatteryMonitor {
+    battery: Battery,
+    /// Moving average of the battery voltage, in mV.
+    average: Option<f32>,
+    low: bool,
+}
+
+impl BatteryMonitor {
+    pub fn new(battery: Battery) -> Self {
+        BatteryMonitor {
+            battery,
+            average: None,
+            low: false,
+        }
+    }
+
+    pub fn battery(&self) -> &Battery {
+        &self.battery
+    }
+
+    /// Add a reading of the voltage at the pin, in mV.
+    pub fn sample(&mut self, pin_millivolts: u16) -> Level {
+        let millivolts = pin_millivolts as f32 * self.battery.divider;
+        let average = match self.average {
+            Some(average) => average + (millivolts - average) * self.battery.smoothing,
+            None => millivolts,
+        };
+        self.average = Some(average);
+        let level = self.level().unwrap();
+        self.low = if self.low {
+            level.percent < self.battery.low.saturating_add(HYSTERESIS)
+        } else {
+            level.percent < self.battery.low
+        };
+        Level {
+            low: self.low,
+            ..level
+        }
+    }
+
+    /// The battery level, `None` before the first reading.
+    pub fn level(&self) -> Option<Level> {
+        let millivolts = self.average?.round().clamp(0.0, u16::MAX as f32) as u16;
+        Some(Level {
+            millivolts,
+            percent: percent(millivolts),
+            low: self.low,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discharge_curve() {
+        assert_eq!(percent(0), 0);
+        assert_eq!(percent(3300), 0);
+        assert_eq!(percent(3625), 15);
+        assert_eq!(percent(3800), 50);
+        assert_eq!(percent(4200), 100);
+        assert_eq!(percent(5000), 100);
+    }
+
+    #[test]
+    fn smooths_and_saves_power() {
+        let mut monitor = BatteryMonitor::new(Battery::default());
+        assert_eq!(monitor.level(), None);
+        let level = monitor.sample(1900);
+        assert_eq!((level.millivolts, level.percent), (3800, 50));
+        // A dip while transmitting barely moves it
+        assert_eq!(monitor.sample(1700).millivolts, 3760);
+        assert!(!monitor.sample(1900).low);
+
+        // Low below 15%, and again not low above 20%
+        let mut level = monitor.sample(1810);
+        while !level.low {
+            level = monitor.sample(1810);
+        }
+        assert!(level.percent < DEFAULT_LOW);
+        assert!(monitor.sample(1830).low);
+        let mut level = monitor.sample(1850);
+        while level.low {
+            assert!(level.percent < DEFAULT_LOW + HYSTERESIS);
+            level = monitor.sample(1850);
+        }
+
+        let battery = Battery::default();
+        assert_eq!(battery.send_delay(&(5..20), false), 5..20);
+        assert_eq!(battery.send_delay(&(5..20), true), LOW_SEND_DELAY_RANGE);
+        assert_eq!(battery.send_delay(&(300..600), true), 300..600);
+    }
+}
@@ -0,0 +1,45 @@
+//! Simulates a parade of badges in virtual time and prints per badge
+//! statistics.
+//!
+//! Usage: `cargo run --release --bin mesh -- [--devices <n>] [--minutes <n>]
+//! [--loss <0..1>] [--spacing <m>] [--range <m>] [--relay-ttl <n>] [--queue <n>]
+//! [--selection <strategy>] [--seed <n>]`
+//!
+//! The strategy is `uniform`, `shuffle`, `least-seen` or `weighted:<weights>`,
+//! like `weighted:classic=5`.
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use vogon::mesh::{Mesh, MeshConfig};
+
+fn main() -> Result<()> {
+    let mut config = MeshConfig::default();
+    let mut minutes = 60;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("Missing value for {}", arg))?;
+        match arg.as_str() {
+            "--devices" => config.devices = value.parse()?,
+            "--minutes" => minutes = value.parse()?,
+            "--loss" => config.packet_loss = value.parse()?,
+            "--spacing" => config.spacing = value.parse()?,
+            "--range" => config.range = value.parse()?,
+            "--relay-ttl" => config.relay_ttl = Some(value.parse()?),
+            "--queue" => config.playback.max_queue = value.parse()?,
+            "--selection" => {
+                config.selection = value.parse()?;
+                config.playback.selection = config.selection.clone();
+            }
+            "--seed" => config.seed = value.parse()?,
+            _ => return Err(anyhow!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let mut mesh = Mesh::new(config);
+    mesh.run_for(Duration::from_secs(minutes * 60));
+    print!("{}", mesh.report());
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Runs a badge on the host, drawing its display as text in the terminal.
+//!
+//! The badge is connected to a handful of simulated peers through an in-memory
+//! broadcast bus that stands in for ESP-NOW.
+//!
+//! Usage: `cargo run --bin simulator -- [--device-id <id>] [--peers <n>]
+//! [--panel <panel>] [--rotation <degrees>]`, see `Panel` for the panels.
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use vogon::corpus;
+use vogon::identity::DeviceId;
+use vogon::library::Library;
+use vogon::peers::{Mac, DEFAULT_PEER_TIMEOUT};
+use vogon::playback::Policy;
+use vogon::protection::Protection;
+use vogon::screen::{Framebuffer, Orientation, Panel, Rotated, Rotation, Screen};
+use vogon::selector::Strategy;
+use vogon::transceiver::{self, Badge, Broadcaster};
+use vogon::TOTAL_DEVICES;
+
+/// Draws to a `Framebuffer` and prints it on every flush.
+struct Terminal(Framebuffer);
+
+impl OriginDimensions for Terminal {
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+}
+
+impl DrawTarget for Terminal {
+    type Color = BinaryColor;
+    type Error = <Framebuffer as DrawTarget>::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.0.draw_iter(pixels)
+    }
+}
+
+impl Screen for Terminal {
+    fn flush(&mut self) -> Result<()> {
+        // Move the cursor home and redraw over the previous frame
+        print!("\x1b[H{}", self.0.to_ascii());
+        Ok(())
+    }
+}
+
+/// A frame and the MAC address of its sender.
+type Frame = (Mac, Vec<u8>);
+
+/// Fake ESP-NOW: every frame is delivered to every other device.
+#[derive(Default)]
+struct Bus {
+    devices: Mutex<Vec<(u8, Sender<Frame>)>>,
+}
+
+impl Bus {
+    fn join(&self, device_id: u8) -> std::sync::mpsc::Receiver<Frame> {
+        let (tx, rx) = channel();
+        self.devices.lock().unwrap().push((device_id, tx));
+        rx
+    }
+
+    fn broadcast(&self, src: u8, frame: &[u8]) {
+        for (device_id, tx) in self.devices.lock().unwrap().iter() {
+            if *device_id != src {
+                let _ = tx.send((mac(src), frame.to_vec()));
+            }
+        }
+    }
+}
+
+/// Made up MAC address of a simulated badge.
+fn mac(device_id: u8) -> Mac {
+    [0x24, 0x0a, 0xc4, 0x00, 0x00, device_id]
+}
+
+fn parse_args() -> Result<(DeviceId, u8, Panel, Rotation)> {
+    let mut device_id = DeviceId::new(1)?;
+    let mut peers = 3;
+    let mut panel = Panel::default();
+    let mut rotation = Rotation::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("Missing value for {}", arg))?;
+        match arg.as_str() {
+            "--device-id" => device_id = value.parse()?,
+            "--peers" => peers = value.parse()?,
+            "--panel" => panel = value.parse()?,
+            "--rotation" => rotation = value.parse()?,
+            _ => return Err(anyhow!("Unknown argument: {}", arg)),
+        }
+    }
+    Ok((device_id, peers, panel, rotation))
+}
+
+/// Run the send loop of a badge on the bus. Peers without a `Badge` send
+/// from their own copy of `poems`.
+fn spawn_sender(
+    bus: Arc<Bus>,
+    device_id: DeviceId,
+    poems: Vec<(u8, String)>,
+    badge: Option<Arc<Badge>>,
+) {
+    std::thread::spawn(move || {
+        let rng = &mut rand::thread_rng();
+        let mut broadcaster = Broadcaster::new(device_id, Strategy::default());
+        let library = Library::new(poems);
+        loop {
+            std::thread::sleep(broadcaster.delay(rng));
+            let packets = match &badge {
+                Some(badge) => broadcaster.next_packets(rng, &badge.library.lock().unwrap()),
+                None => broadcaster.next_packets(rng, &library),
+            };
+            let Some(packets) = packets else {
+                continue;
+            };
+            for packet in packets {
+                bus.broadcast(device_id.get(), &packet.serialize());
+            }
+            if let Some(badge) = &badge {
+                badge.counters.lock().unwrap().sent += 1;
+            }
+        }
+    });
+}
+
+fn main() -> Result<()> {
+    let (device_id, peers, panel, rotation) = parse_args()?;
+    let poems = corpus::poems();
+
+    let start = Instant::now();
+    let bus = Arc::new(Bus::default());
+    let badge = Arc::new(Badge::new(
+        device_id,
+        mac(device_id.get()),
+        Library::new(poems.clone()),
+        DEFAULT_PEER_TIMEOUT,
+    ));
+
+    // Receive "callback"
+    let (tx, rx) = channel();
+    let inbox = bus.join(device_id.get());
+    let recv_badge = badge.clone();
+    std::thread::spawn(move || {
+        for (mac, frame) in inbox {
+            if let Some(poem) = recv_badge.receive(mac, None, &frame, start.elapsed()) {
+                tx.send(poem).unwrap();
+            }
+        }
+    });
+
+    spawn_sender(bus.clone(), device_id, vec![], Some(badge.clone()));
+    for peer in (1..=TOTAL_DEVICES)
+        .filter(|id| *id != device_id.get())
+        .take(peers as usize)
+    {
+        spawn_sender(bus.clone(), DeviceId::new(peer)?, poems.clone(), None);
+    }
+
+    // Clear the terminal
+    print!("\x1b[2J");
+    let orientation = Orientation {
+        rotation,
+        mirrored: false,
+    };
+    let mut display = Rotated::new(
+        Terminal(Framebuffer::new(panel.size)),
+        Arc::new(Mutex::new(orientation)),
+    );
+    let id = transceiver::run(
+        &mut display,
+        &badge,
+        &rx,
+        Policy::default(),
+        Protection::default(),
+        start,
+    )?;
+    Err(anyhow!(
+        "Device id {} is used by another badge, restart with --device-id {}",
+        device_id,
+        id
+    ))
+}
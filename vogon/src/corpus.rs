@@ -0,0 +1,56 @@
+//! The poems every badge is built with.
+//!
+//! The build script reads them from `assets/poems.txt`, see `format` for the
+//! file format. Poem ids in packets are the ids in that file, so editing it
+//! doesn't change which poem an id refers to.
+pub mod format;
+pub mod validate;
+
+/// A poem and what is known about it.
+#[derive(Debug)]
+pub struct Entry {
+    pub id: u8,
+    pub title: &'static str,
+    pub author: Option<&'static str>,
+    pub source: Option<&'static str>,
+    pub tags: &'static [&'static str],
+    pub body: &'static str,
+}
+
+impl Entry {
+    /// Who to thank for the poem, the author or else where it came from.
+    pub fn credit(&self) -> Option<&'static str> {
+        self.author.or(self.source)
+    }
+}
+
+/// All poems, sorted by id.
+pub static POEMS: &[Entry] = include!(concat!(env!("OUT_DIR"), "/poems.rs"));
+
+pub fn get(id: u8) -> Option<&'static Entry> {
+    POEMS
+        .binary_search_by_key(&id, |entry| entry.id)
+        .ok()
+        .map(|i| &POEMS[i])
+}
+
+/// Ids and text of all poems, to fill a `Library` with.
+pub fn poems() -> Vec<(u8, String)> {
+    POEMS
+        .iter()
+        .map(|entry| (entry.id, entry.body.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_by_id() {
+        assert!(POEMS.windows(2).all(|w| w[0].id < w[1].id));
+        let first = get(0).unwrap();
+        assert!(first.body.starts_with("Oh freddled gruntbuggly"));
+        assert_eq!(first.credit(), Some("Prostetnic Vogon Jeltz"));
+    }
+}
@@ -0,0 +1,258 @@
+//! The format of `assets/poems.txt`.
+//!
+//! Every poem starts with a header between `+++` lines, holding TOML style
+//! `key = value` pairs, followed by its text:
+//!
+//! ```text
+//! +++
+//! id = 0
+//! title = "Ode to a Small Lump of Green Putty"
+//! author = "Prostetnic Vogon Jeltz"
+//! source = "The Hitchhiker's Guide to the Galaxy"
+//! tags = ["classic"]
+//! +++
+//! Oh freddled gruntbuggly,
+//! Thy micturations are to me,
+//! ```
+//!
+//! Only `id` is required, the title defaults to the first line of the poem.
+//! The text runs until the next header, so it can contain empty lines.
+//!
+//! This module is also compiled into the build script, so it only uses std.
+use std::collections::BTreeMap;
+use std::fmt;
+
+const DELIMITER: &str = "+++";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poem {
+    pub id: u8,
+    pub title: String,
+    pub author: Option<String>,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+    pub body: String,
+    /// Line the header starts on.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn error(line: usize, message: impl Into<String>) -> ParseError {
+    ParseError {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Parse a poem file, in the order of the file.
+pub fn parse(text: &str) -> Result<Vec<Poem>, ParseError> {
+    let mut poems: Vec<Poem> = vec![];
+    let mut ids = BTreeMap::new();
+    let mut lines = text.lines().zip(1..).peekable();
+
+    while let Some((line, start)) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.trim_end() != DELIMITER {
+            return Err(error(start, format!("expected {:?}", DELIMITER)));
+        }
+
+        let (mut id, mut title, mut author, mut source, mut tags) =
+            (None, None, None, None, vec![]);
+        loop {
+            let (line, n) = lines
+                .next()
+                .ok_or_else(|| error(start, "header isn't closed"))?;
+            let line = line.trim();
+            if line == DELIMITER {
+                break;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(n, "expected key = value"))?;
+            match key.trim() {
+                "id" => {
+                    let value = value.trim();
+                    id = Some(
+                        value
+                            .parse::<u8>()
+                            .map_err(|_| error(n, format!("invalid id {}", value)))?,
+                    );
+                }
+                "title" => title = Some(string(value, n)?),
+                "author" => author = Some(string(value, n)?),
+                "source" => source = Some(string(value, n)?),
+                "tags" => tags = list(value, n)?,
+                other => return Err(error(n, format!("unknown key {:?}", other))),
+            }
+        }
+
+        let mut body = vec![];
+        while let Some((line, _)) = lines.next_if(|(line, _)| line.trim_end() != DELIMITER) {
+            body.push(line);
+        }
+        while body.last().is_some_and(|line| line.trim().is_empty()) {
+            body.pop();
+        }
+        let body = body
+            .into_iter()
+            .skip_while(|line| line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let id = id.ok_or_else(|| error(start, "missing id"))?;
+        if let Some(other) = ids.insert(id, start) {
+            return Err(error(
+                start,
+                format!("id {} is already used on line {}", id, other),
+            ));
+        }
+        if body.is_empty() {
+            return Err(error(start, format!("poem {} has no text", id)));
+        }
+        poems.push(Poem {
+            id,
+            title: title.unwrap_or_else(|| body.lines().next().unwrap().trim().to_string()),
+            author,
+            source,
+            tags,
+            body,
+            line: start,
+        });
+    }
+    Ok(poems)
+}
+
+/// A quoted string, returning the rest of the input after it.
+fn quoted(s: &str, line: usize) -> Result<(String, &str), ParseError> {
+    let s = s
+        .strip_prefix('"')
+        .ok_or_else(|| error(line, "expected a quoted string"))?;
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &s[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => out.push('\n'),
+                Some((_, c @ ('"' | '\\'))) => out.push(c),
+                _ => return Err(error(line, "invalid escape")),
+            },
+            c => out.push(c),
+        }
+    }
+    Err(error(line, "string isn't closed"))
+}
+
+fn string(value: &str, line: usize) -> Result<String, ParseError> {
+    let (s, rest) = quoted(value.trim(), line)?;
+    if !rest.trim().is_empty() {
+        return Err(error(line, format!("unexpected {:?}", rest.trim())));
+    }
+    Ok(s)
+}
+
+/// A list of strings, like `["a", "b"]`.
+fn list(value: &str, line: usize) -> Result<Vec<String>, ParseError> {
+    let mut rest = value
+        .trim()
+        .strip_prefix('[')
+        .ok_or_else(|| error(line, "expected a list"))?
+        .trim_start();
+    let mut items = vec![];
+    while !rest.starts_with(']') {
+        let (item, after) = quoted(rest, line)?;
+        items.push(item);
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if !rest.starts_with(']') {
+            return Err(error(line, "expected , or ]"));
+        }
+    }
+    if !rest[1..].trim().is_empty() {
+        return Err(error(line, format!("unexpected {:?}", rest[1..].trim())));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poems() {
+        let text = r#"
++++
+id = 3
+title = "Ode to \"Putty\""
+author = "Prostetnic Vogon Jeltz"
+tags = ["classic", "putty"]
++++
+Oh freddled gruntbuggly,
+
+Thy micturations are to me
++++
+# Just an id
+id = 0
++++
+  Groop, I implore thee
+"#;
+        let poems = parse(text).unwrap();
+        assert_eq!(poems.len(), 2);
+        assert_eq!(poems[0].id, 3);
+        assert_eq!(poems[0].title, "Ode to \"Putty\"");
+        assert_eq!(poems[0].author.as_deref(), Some("Prostetnic Vogon Jeltz"));
+        assert_eq!(poems[0].source, None);
+        assert_eq!(poems[0].tags, vec!["classic", "putty"]);
+        assert_eq!(
+            poems[0].body,
+            "Oh freddled gruntbuggly,\n\nThy micturations are to me"
+        );
+        assert_eq!(poems[1].title, "Groop, I implore thee");
+        assert_eq!(poems[1].body, "  Groop, I implore thee");
+        assert_eq!(poems[1].line, 11);
+    }
+
+    #[test]
+    fn errors() {
+        let message = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(message("Oh freddled"), "line 1: expected \"+++\"");
+        assert_eq!(message("+++\nid = 1\n"), "line 1: header isn't closed");
+        assert_eq!(message("+++\ntitle = \"a\"\n+++\nb"), "line 1: missing id");
+        assert_eq!(message("+++\nid = 256\n+++\nb"), "line 2: invalid id 256");
+        assert_eq!(
+            message("+++\nid = 1\n+++\n\n"),
+            "line 1: poem 1 has no text"
+        );
+        assert_eq!(
+            message("+++\nid = 1\ncolour = \"green\"\n+++\nb"),
+            "line 3: unknown key \"colour\""
+        );
+        assert_eq!(
+            message("+++\nid = 1\ntags = [\"a\" \"b\"]\n+++\nb"),
+            "line 3: expected , or ]"
+        );
+        assert_eq!(
+            message("+++\nid = 1\n+++\nb\n+++\nid = 1\n+++\nc"),
+            "line 5: id 1 is already used on line 1"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Checks that the poems can be shown on the badge. The build script runs
+//! them, so a poem that would render badly breaks the build.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::format::Poem;
+use crate::layout::{self, Layout};
+use crate::{font, glyphs};
+
+/// Poem ids are a `u8`.
+pub const MAX_POEMS: usize = u8::MAX as usize + 1;
+/// The longest lines that are typed before a poem, where it came from and
+/// how many poems wait after it. See `display_poem` in `transceiver`.
+pub const INTRO: &str = "Received from: 42/42\nvia 255 hops\nqueued: 255\n";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    TooManyPoems(usize),
+    /// The poem scrolls more than `layout::MAX_POEM_LINES` lines.
+    TooLong {
+        id: u8,
+        line: usize,
+        lines: usize,
+    },
+    /// A character has no glyph or look-alike.
+    MissingGlyph {
+        id: u8,
+        line: usize,
+        c: char,
+    },
+    /// The poem has the same text as another one.
+    Duplicate {
+        id: u8,
+        line: usize,
+        other: u8,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::TooManyPoems(n) => {
+                write!(f, "{} poems, there can be at most {}", n, MAX_POEMS)
+            }
+            Problem::TooLong { id, line, lines } => write!(
+                f,
+                "line {}: poem {} is {} lines on the screen, at most {} fit",
+                line,
+                id,
+                lines,
+                layout::MAX_POEM_LINES
+            ),
+            Problem::MissingGlyph { id, line, c } => write!(
+                f,
+                "line {}: poem {} uses {:?} (U+{:04X}), which the font doesn't have",
+                line, id, c, *c as u32
+            ),
+            Problem::Duplicate { id, line, other } => {
+                write!(
+                    f,
+                    "line {}: poem {} is the same as poem {}",
+                    line, id, other
+                )
+            }
+        }
+    }
+}
+
+/// The text of a poem as it is typed on the screen, at its longest.
+fn screen_text(poem: &Poem) -> String {
+    let credit = poem.author.as_ref().or(poem.source.as_ref());
+    match credit {
+        Some(credit) => format!("{}{}\nby {}\n{}", INTRO, poem.title, credit, poem.body),
+        None => format!("{}{}\n{}", INTRO, poem.title, poem.body),
+    }
+}
+
+/// Lines a text takes on a screen `width` pixels wide.
+fn lines(text: &str, width: u32) -> usize {
+    Layout::new(&font::character_style(), &font::text_style(), text, width)
+        .lines
+        .len()
+}
+
+/// Everything that is wrong with the poems on a screen `width` pixels wide,
+/// in the order of the file.
+pub fn validate(poems: &[Poem], width: u32) -> Vec<Problem> {
+    let mut problems = vec![];
+    if poems.len() > MAX_POEMS {
+        problems.push(Problem::TooManyPoems(poems.len()));
+    }
+
+    let mut bodies = BTreeMap::new();
+    for poem in poems {
+        let (id, line) = (poem.id, poem.line);
+        let text = screen_text(poem);
+        let lines = lines(&glyphs::normalize(&text), width);
+        if lines > layout::MAX_POEM_LINES {
+            problems.push(Problem::TooLong { id, line, lines });
+        }
+
+        let mut missing: Vec<char> = text.chars().filter(|c| !glyphs::has_glyph(*c)).collect();
+        missing.sort_unstable();
+        missing.dedup();
+        problems.extend(
+            missing
+                .into_iter()
+                .map(|c| Problem::MissingGlyph { id, line, c }),
+        );
+
+        if let Some(other) = bodies.insert(poem.body.trim(), id) {
+            problems.push(Problem::Duplicate { id, line, other });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus::format::parse;
+
+    #[test]
+    fn problems() {
+        let long = "Grupping, glupping, ".repeat(100);
+        let text = format!(
+            "+++\nid = 0\n+++\nOh freddled gruntbuggly\n\
+             +++\nid = 1\n+++\nThy micturations ☃ are to me\n\
+             +++\nid = 2\n+++\nOh freddled gruntbuggly\n\
+             +++\nid = 3\ntitle = \"Grupping\"\n+++\n{}\n",
+            long
+        );
+        let poems = parse(&text).unwrap();
+        assert_eq!(
+            validate(&poems, 128),
+            vec![
+                Problem::MissingGlyph {
+                    id: 1,
+                    line: 5,
+                    c: '☃'
+                },
+                Problem::Duplicate {
+                    id: 2,
+                    line: 9,
+                    other: 0
+                },
+                Problem::TooLong {
+                    id: 3,
+                    line: 13,
+                    lines: INTRO.lines().count() + 1 + lines(&long, 128)
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Effects built on the animation engine in `animation`.
+//!
+//! Every effect owns what it draws and moves it a bit on every frame, so
+//! effects can be combined and stopped halfway.
+use std::time::Duration;
+
+use crate::animation::{Animation, Sequence, Status};
+use crate::font::{character_style, text_style};
+use crate::glyphs;
+use crate::layout::Layout;
+use crate::screen::Screen;
+use crate::utils::screen_center;
+use embedded_graphics::pixelcolor::*;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle};
+use embedded_graphics::text::Text;
+
+/// Time it takes to scroll one pixel.
+const SCROLL_STEP: Duration = Duration::from_millis(40);
+
+/// Time it takes to type one character.
+const TYPE_STEP: Duration = Duration::from_millis(70);
+
+/// Shows and hides a drawable a number of times.
+pub struct Blink<D> {
+    d: D,
+    times: u32,
+    period: Duration,
+    /// Whether to show it once more at the end.
+    keep: bool,
+    elapsed: Duration,
+    phase: Option<u32>,
+}
+
+impl<D> Blink<D> {
+    pub fn new(d: D, times: u32, period: Duration, keep: bool) -> Self {
+        Blink {
+            d,
+            times,
+            period,
+            keep,
+            elapsed: Duration::ZERO,
+            phase: None,
+        }
+    }
+}
+
+impl<S, D> Animation<S> for Blink<D>
+where
+    S: Screen,
+    D: Drawable<Color = BinaryColor>,
+{
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status {
+        if self.phase.is_some() {
+            self.elapsed += dt;
+        }
+        // Even phases show the drawable, odd ones clear it
+        let phase = (self.elapsed.as_millis() / self.period.as_millis().max(1)) as u32;
+        if phase >= self.times * 2 {
+            display.clear(BinaryColor::Off).unwrap();
+            if self.keep {
+                self.d.draw(display).unwrap();
+            }
+            return Status::Done;
+        }
+        if self.phase != Some(phase) {
+            self.phase = Some(phase);
+            display.clear(BinaryColor::Off).unwrap();
+            if phase % 2 == 0 {
+                self.d.draw(display).unwrap();
+            }
+        }
+        Status::Running
+    }
+}
+
+/// Types text like a teletype, moving the lines up when the screen is full.
+pub struct TypeText {
+    text: String,
+    /// The laid out text, set on the first frame when the size of the
+    /// display is known.
+    typing: Option<Typing>,
+    elapsed: Duration,
+}
+
+struct Typing {
+    chars: Vec<char>,
+    line_height: u32,
+    max_lines: usize,
+    typed: usize,
+    /// What is on the screen.
+    out: String,
+    lines: usize,
+}
+
+impl TypeText {
+    pub fn new(s: &str) -> Self {
+        TypeText {
+            text: s.to_string(),
+            typing: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl<S: Screen> Animation<S> for TypeText {
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status {
+        let character_style = character_style();
+        let text_style = text_style();
+
+        let typing = match &mut self.typing {
+            Some(typing) => {
+                self.elapsed += dt;
+                typing
+            }
+            None => {
+                let size = display.bounding_box().size;
+                let layout = Layout::new(
+                    &character_style,
+                    &text_style,
+                    &glyphs::normalize(&self.text),
+                    size.width,
+                );
+                self.typing.insert(Typing {
+                    chars: layout.text().chars().collect(),
+                    line_height: layout.line_height,
+                    max_lines: layout.lines_per_screen(size.height) - 1,
+                    typed: 0,
+                    out: String::new(),
+                    lines: 0,
+                })
+            }
+        };
+
+        // The first character is typed right away
+        let due = (self.elapsed.as_millis() / TYPE_STEP.as_millis()) as usize + 1;
+        while typing.typed < due.min(typing.chars.len()) {
+            let c = typing.chars[typing.typed];
+            typing.typed += 1;
+            typing.out.push(c);
+
+            if c == '\n' {
+                typing.lines += 1;
+            }
+
+            // Move up when the screen is full
+            if typing.out.ends_with('\n') && typing.lines > typing.max_lines {
+                let mut split = typing.out.splitn(2, '\n');
+                split.next();
+                typing.out = split.next().unwrap().to_string();
+                // clear display
+                display.clear(BinaryColor::Off).unwrap();
+
+                let text = Text::with_text_style(
+                    &typing.out,
+                    Point::new(0, 0),
+                    character_style,
+                    text_style,
+                );
+                text.draw(display).unwrap();
+            } else {
+                // Only the line that is being typed changed, so just draw that one
+                let row = typing.out.matches('\n').count() as u32;
+                let line = typing.out.rsplit('\n').next().unwrap();
+                let y = row * typing.line_height;
+                let text = Text::with_text_style(
+                    line,
+                    Point::new(0, y as i32),
+                    character_style,
+                    text_style,
+                );
+                text.draw(display).unwrap();
+            }
+        }
+
+        if typing.typed == typing.chars.len() {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+}
+
+/// Moves a drawable in a straight line, one pixel at a time.
+pub struct Scroll<D> {
+    d: D,
+    to: Point,
+    step: Point,
+    /// Pixels left to move.
+    distance: u32,
+    /// A box one pixel larger than the drawable, to clear the previous frame
+    /// instead of completely clearing the display between every frame.
+    clear: Rectangle,
+    elapsed: Duration,
+}
+
+impl<D: Dimensions + Transform> Scroll<D> {
+    /// Scroll `d` so its top left corner moves from `from` to `to`.
+    pub fn new(mut d: D, from: Point, to: Point) -> Self {
+        // Calculate the distance between from and to
+        let distance_x = (from.x - to.x).abs();
+        let distance_y = (from.y - to.y).abs();
+        let distance = ((distance_x.pow(2) + distance_y.pow(2)) as f32).sqrt() as u32;
+
+        // Move the drawable to `from`
+        d.translate_mut(from - d.bounding_box().top_left);
+        let bb = d.bounding_box();
+
+        Scroll {
+            d,
+            to,
+            step: Point::new(get_step(from.x, to.x), get_step(from.y, to.y)),
+            distance,
+            clear: Rectangle::new(bb.top_left - Point::new(1, 1), bb.size + Size::new(2, 2)),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// The drawable where it will be at the end.
+    pub fn end(&self) -> D
+    where
+        D: Clone,
+    {
+        let mut d = self.d.clone();
+        d.translate_mut(self.to - d.bounding_box().top_left);
+        d
+    }
+}
+
+impl<S, D> Animation<S> for Scroll<D>
+where
+    S: Screen,
+    D: Dimensions + Transform + Drawable<Color = BinaryColor>,
+{
+    fn frame(&mut self, display: &mut S, dt: Duration) -> Status {
+        self.elapsed += dt;
+        let style = PrimitiveStyleBuilder::new()
+            .fill_color(BinaryColor::Off)
+            .build();
+        while self.distance > 0 && self.elapsed >= SCROLL_STEP {
+            self.elapsed -= SCROLL_STEP;
+            self.distance -= 1;
+            self.clear.translate_mut(self.step);
+            self.d.translate_mut(self.step);
+        }
+        self.clear.into_styled(style).draw(display).unwrap();
+        self.d.draw(display).unwrap();
+        if self.distance == 0 {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+}
+
+fn get_step(from: i32, to: i32) -> i32 {
+    match from.cmp(&to) {
+        std::cmp::Ordering::Less => 1,
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => -1,
+    }
+}
+
+/// Scroll in from the right and out to the left, on a screen of `screen`.
+pub fn left<'a, S, D>(screen: Size, d: D) -> Sequence<'a, S>
+where
+    S: Screen,
+    D: Dimensions + Transform + Drawable<Color = BinaryColor> + Clone + 'a,
+{
+    let scroll_in = left_in(screen, d);
+    let scroll_out = left_out(screen, scroll_in.end());
+    Sequence::new().then(scroll_in).then(scroll_out)
+}
+
+pub fn left_in<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let center = screen_center(screen, &d);
+    Scroll::new(d, Point::new(screen.width as i32, center.y), center)
+}
+
+pub fn left_out<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let from = d.bounding_box().top_left;
+    let to = Point::new(
+        -(d.bounding_box().size.width as i32),
+        screen_center(screen, &d).y,
+    );
+    Scroll::new(d, from, to)
+}
+
+/// Scroll in from the left and out to the right.
+pub fn right<'a, S, D>(screen: Size, d: D) -> Sequence<'a, S>
+where
+    S: Screen,
+    D: Dimensions + Transform + Drawable<Color = BinaryColor> + Clone + 'a,
+{
+    let scroll_in = right_in(screen, d);
+    let scroll_out = right_out(screen, scroll_in.end());
+    Sequence::new().then(scroll_in).then(scroll_out)
+}
+
+pub fn right_in<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let center = screen_center(screen, &d);
+    let from = Point::new(-(d.bounding_box().size.width as i32), center.y);
+    Scroll::new(d, from, center)
+}
+
+pub fn right_out<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let from = d.bounding_box().top_left;
+    let to = Point::new(screen.width as i32, screen_center(screen, &d).y);
+    Scroll::new(d, from, to)
+}
+
+/// Scroll in from the bottom and out at the top.
+pub fn up<'a, S, D>(screen: Size, d: D) -> Sequence<'a, S>
+where
+    S: Screen,
+    D: Dimensions + Transform + Drawable<Color = BinaryColor> + Clone + 'a,
+{
+    let scroll_in = up_in(screen, d);
+    let scroll_out = up_out(screen, scroll_in.end());
+    Sequence::new().then(scroll_in).then(scroll_out)
+}
+
+pub fn up_in<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let center = screen_center(screen, &d);
+    Scroll::new(d, Point::new(center.x, screen.height as i32), center)
+}
+
+pub fn up_out<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let from = d.bounding_box().top_left;
+    let to = Point::new(
+        screen_center(screen, &d).x,
+        -(d.bounding_box().size.height as i32),
+    );
+    Scroll::new(d, from, to)
+}
+
+/// Scroll in from the top and out at the bottom.
+pub fn down<'a, S, D>(screen: Size, d: D) -> Sequence<'a, S>
+where
+    S: Screen,
+    D: Dimensions + Transform + Drawable<Color = BinaryColor> + Clone + 'a,
+{
+    let scroll_in = down_in(screen, d);
+    let scroll_out = down_out(screen, scroll_in.end());
+    Sequence::new().then(scroll_in).then(scroll_out)
+}
+
+pub fn down_in<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let center = screen_center(screen, &d);
+    let from = Point::new(center.x, -(d.bounding_box().size.height as i32));
+    Scroll::new(d, from, center)
+}
+
+pub fn down_out<D: Dimensions + Transform>(screen: Size, d: D) -> Scroll<D> {
+    let from = d.bounding_box().top_left;
+    let to = Point::new(screen_center(screen, &d).x, screen.height as i32);
+    Scroll::new(d, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{play, Parallel, Player};
+    use crate::screen::{Framebuffer, HeadlessScreen};
+    use embedded_graphics::primitives::PrimitiveStyle;
+
+    fn square() -> embedded_graphics::primitives::Styled<Rectangle, PrimitiveStyle<BinaryColor>> {
+        Rectangle::new(Point::zero(), Size::new(8, 8))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    }
+
+    #[test]
+    fn scrolls_to_the_center() {
+        let mut display = Framebuffer::default();
+        let screen = display.size();
+        play(&mut display, up_in(screen, square())).unwrap();
+        // Centered on the 128x32 screen
+        assert!(display.pixel(60, 12) && display.pixel(67, 19));
+        assert!(!display.pixel(60, 20) && !display.pixel(59, 12));
+
+        play(
+            &mut display,
+            left_out(screen, up_in(screen, square()).end()),
+        )
+        .unwrap();
+        assert!(!display.pixel(60, 12));
+
+        // And on a taller one
+        let mut display = Framebuffer::new(Size::new(128, 64));
+        let screen = display.size();
+        play(&mut display, down_in(screen, square())).unwrap();
+        assert!(display.pixel(60, 28) && display.pixel(67, 35));
+        assert!(!display.pixel(60, 12));
+    }
+
+    #[test]
+    fn takes_as_long_as_before() {
+        // One pixel every 40ms, from the bottom to the center and off the top
+        let mut display = HeadlessScreen::default();
+        let screen = display.size();
+        play(&mut display, up(screen, square())).unwrap();
+        assert_eq!(display.elapsed, SCROLL_STEP * ((32 - 12) + (12 + 8) - 1));
+    }
+
+    #[test]
+    fn cancelled_halfway() {
+        let mut display = Framebuffer::default();
+        let mut animation = Parallel::new()
+            .with(right(display.size(), square()))
+            .with(TypeText::new("Oh freddled gruntbuggly"));
+        let mut frames = 0;
+        let finished = Player::default()
+            .play(&mut display, &mut animation, || {
+                frames += 1;
+                frames > 5
+            })
+            .unwrap();
+        assert!(!finished);
+        // The square moved in by 5 pixels and stays there
+        assert!(display.pixel(4, 12) && !display.pixel(5, 12));
+    }
+
+    #[test]
+    fn blinks() {
+        let mut display = HeadlessScreen::default();
+        play(
+            &mut display,
+            Blink::new(square(), 3, Duration::from_millis(500), true),
+        )
+        .unwrap();
+        assert_eq!(display.elapsed, Duration::from_millis(3000));
+
+        let mut display = Framebuffer::default();
+        play(
+            &mut display,
+            Blink::new(square(), 3, Duration::from_millis(500), false),
+        )
+        .unwrap();
+        assert!(!display.pixel(0, 0));
+    }
+}
@@ -0,0 +1,19 @@
+//! The font all text is drawn in, see `glyphs` for the characters it has.
+use embedded_graphics::mono_font::{iso_8859_1, MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::text::{Alignment, Baseline, LineHeight, TextStyle, TextStyleBuilder};
+
+pub const FONT: &MonoFont<'static> = &iso_8859_1::FONT_5X7;
+
+pub fn character_style() -> MonoTextStyle<'static, BinaryColor> {
+    MonoTextStyle::new(FONT, BinaryColor::On)
+}
+
+/// Style of left aligned text, like poems.
+pub fn text_style() -> TextStyle {
+    TextStyleBuilder::new()
+        .alignment(Alignment::Left)
+        .line_height(LineHeight::Percent(110))
+        .baseline(Baseline::Top)
+        .build()
+}
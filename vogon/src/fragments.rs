@@ -0,0 +1,290 @@
+//! Reassembly of poems that were sent as text in multiple fragments.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::protocol::{content_hash, MAX_FRAGMENTS, MAX_TEXT_LEN};
+
+/// Give up on a poem if its fragments didn't all arrive within this time.
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Poems that are reassembled at the same time, the oldest is dropped when
+/// another one starts.
+const MAX_PARTIAL: usize = 8;
+/// Bytes of all poems that are reassembled at the same time, the oldest are
+/// dropped to make room for a new fragment.
+const MAX_BUFFERED: usize = 4 * MAX_TEXT_LEN;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FragmentError {
+    /// More fragments than the longest poem takes.
+    TooManyFragments(u8),
+    /// The index is past the number of fragments.
+    BadIndex {
+        index: u8,
+        count: u8,
+    },
+    /// The fragment has a different count than the ones before.
+    CountMismatch {
+        expected: u8,
+        actual: u8,
+    },
+    /// The reassembled text doesn't match the hash.
+    BadHash {
+        expected: u32,
+        actual: u32,
+    },
+    NotUtf8,
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentError::TooManyFragments(count) => {
+                write!(f, "{} fragments, at most {}", count, MAX_FRAGMENTS)
+            }
+            FragmentError::BadIndex { index, count } => {
+                write!(f, "fragment {} of {}", index, count)
+            }
+            FragmentError::CountMismatch { expected, actual } => {
+                write!(f, "fragment count changed from {} to {}", expected, actual)
+            }
+            FragmentError::BadHash { expected, actual } => write!(
+                f,
+                "bad content hash (expected {:08x}, got {:08x})",
+                expected, actual
+            ),
+            FragmentError::NotUtf8 => write!(f, "poem isn't valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    started: Duration,
+}
+
+impl Partial {
+    /// Bytes of the fragments that arrived.
+    fn len(&self) -> usize {
+        self.fragments.iter().flatten().map(Vec::len).sum()
+    }
+}
+
+/// Collects fragments, which can arrive in any order, until a poem is
+/// complete.
+#[derive(Default)]
+pub struct Reassembler {
+    /// By sender and content hash.
+    partial: BTreeMap<(u8, u32), Partial>,
+    /// Bytes in `partial`.
+    buffered: usize,
+    /// Poems that timed out or were dropped before they were complete.
+    pub expired: u32,
+}
+
+impl Reassembler {
+    /// Add a fragment from badge `src`, returning the text once all fragments
+    /// of the poem arrived.
+    pub fn push(
+        &mut self,
+        src: u8,
+        hash: u32,
+        index: u8,
+        count: u8,
+        data: &[u8],
+        now: Duration,
+    ) -> Result<Option<String>, FragmentError> {
+        self.expire(now);
+        if count as usize > MAX_FRAGMENTS {
+            return Err(FragmentError::TooManyFragments(count));
+        }
+        if index >= count {
+            return Err(FragmentError::BadIndex { index, count });
+        }
+
+        let key = (src, hash);
+        if !self.partial.contains_key(&key) && self.partial.len() == MAX_PARTIAL {
+            self.drop_oldest(key);
+        }
+        while self.buffered + data.len() > MAX_BUFFERED && self.drop_oldest(key) {}
+        let partial = self.partial.entry((src, hash)).or_insert_with(|| Partial {
+            fragments: vec![None; count as usize],
+            started: now,
+        });
+        if partial.fragments.len() != count as usize {
+            let expected = partial.fragments.len() as u8;
+            self.remove(key);
+            return Err(FragmentError::CountMismatch {
+                expected,
+                actual: count,
+            });
+        }
+        let replaced = partial.fragments[index as usize].replace(data.to_vec());
+        self.buffered = self.buffered + data.len() - replaced.map_or(0, |r| r.len());
+        if partial.fragments.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let partial = self.remove(key).unwrap();
+        let text: Vec<u8> = partial.fragments.into_iter().flatten().flatten().collect();
+        let actual = content_hash(&text);
+        if actual != hash {
+            return Err(FragmentError::BadHash {
+                expected: hash,
+                actual,
+            });
+        }
+        String::from_utf8(text)
+            .map(Some)
+            .map_err(|_| FragmentError::NotUtf8)
+    }
+
+    /// Drop poems that didn't complete within `REASSEMBLY_TIMEOUT`.
+    pub fn expire(&mut self, now: Duration) {
+        let before = self.partial.len();
+        self.partial
+            .retain(|_, p| now.saturating_sub(p.started) <= REASSEMBLY_TIMEOUT);
+        self.expired += (before - self.partial.len()) as u32;
+        self.buffered = self.partial.values().map(Partial::len).sum();
+    }
+
+    /// Drop the poem that started first, other than `keep`. Returns false if
+    /// there is none.
+    fn drop_oldest(&mut self, keep: (u8, u32)) -> bool {
+        let oldest = self
+            .partial
+            .iter()
+            .filter(|(key, _)| **key != keep)
+            .min_by_key(|(_, p)| p.started)
+            .map(|(key, _)| *key);
+        match oldest {
+            Some(key) => {
+                self.remove(key);
+                self.expired += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, key: (u8, u32)) -> Option<Partial> {
+        let partial = self.partial.remove(&key)?;
+        self.buffered -= partial.len();
+        Some(partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{fragment, Message, MAX_FRAGMENT_LEN};
+
+    fn fragments(text: &str) -> Vec<(u32, u8, u8, Vec<u8>)> {
+        fragment(text)
+            .unwrap()
+            .into_iter()
+            .map(|m| match m {
+                Message::Fragment {
+                    hash,
+                    index,
+                    count,
+                    data,
+                } => (hash, index, count, data),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassembles_out_of_order() {
+        let text = "Grupping, glupping, ".repeat(40);
+        let mut reassembler = Reassembler::default();
+        let mut fragments = fragments(&text);
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for (hash, index, count, data) in fragments {
+            let result = reassembler.push(1, hash, index, count, &data, Duration::ZERO);
+            assert_eq!(result, Ok(None));
+        }
+        let (hash, index, count, data) = last;
+        assert_eq!(
+            reassembler.push(1, hash, index, count, &data, Duration::ZERO),
+            Ok(Some(text))
+        );
+    }
+
+    #[test]
+    fn times_out() {
+        let text = "Bleem miserable venchit! ".repeat(20);
+        let mut reassembler = Reassembler::default();
+        let fragments = fragments(&text);
+        let (hash, index, count, data) = &fragments[0];
+        assert_eq!(
+            reassembler.push(1, *hash, *index, *count, data, Duration::ZERO),
+            Ok(None)
+        );
+
+        let later = REASSEMBLY_TIMEOUT + Duration::from_secs(1);
+        for (hash, index, count, data) in &fragments[1..] {
+            assert_eq!(
+                reassembler.push(1, *hash, *index, *count, data, later),
+                Ok(None)
+            );
+        }
+        assert_eq!(reassembler.expired, 1);
+    }
+
+    #[test]
+    fn limits_memory() {
+        let mut reassembler = Reassembler::default();
+        let count = MAX_FRAGMENTS as u8 + 1;
+        assert_eq!(
+            reassembler.push(1, 0, 0, count, b"", Duration::ZERO),
+            Err(FragmentError::TooManyFragments(count))
+        );
+        assert!(reassembler.partial.is_empty());
+
+        // Poems that never complete, the oldest make way for new ones
+        let data = [b'x'; MAX_FRAGMENT_LEN];
+        let count = MAX_FRAGMENTS as u8;
+        for hash in 0..5 {
+            for index in 0..count - 1 {
+                let now = Duration::from_millis(hash as u64);
+                let result = reassembler.push(1, hash, index, count, &data, now);
+                assert_eq!(result, Ok(None));
+                assert!(reassembler.buffered <= MAX_BUFFERED);
+            }
+        }
+        assert!(reassembler.expired > 0);
+        assert!(reassembler.partial.contains_key(&(1, 4)));
+        let buffered: usize = reassembler.partial.values().map(Partial::len).sum();
+        assert_eq!(reassembler.buffered, buffered);
+    }
+
+    #[test]
+    fn rejects_bad_fragments() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(
+            reassembler.push(1, 0, 2, 2, b"", Duration::ZERO),
+            Err(FragmentError::BadIndex { index: 2, count: 2 })
+        );
+        assert_eq!(
+            reassembler.push(1, 0, 0, 1, b"poem", Duration::ZERO),
+            Err(FragmentError::BadHash {
+                expected: 0,
+                actual: content_hash(b"poem")
+            })
+        );
+        assert_eq!(reassembler.push(1, 0, 0, 2, b"", Duration::ZERO), Ok(None));
+        assert_eq!(
+            reassembler.push(1, 0, 1, 3, b"", Duration::ZERO),
+            Err(FragmentError::CountMismatch {
+                expected: 2,
+                actual: 3
+            })
+        );
+    }
+}
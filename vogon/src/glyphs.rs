@@ -0,0 +1,85 @@
+//! Which characters can be drawn.
+//!
+//! Text is drawn in the Latin-1 version of `FONT_5X7`, so most accented
+//! letters have glyphs. Other characters that turn up
+//! in poems, like curly quotes and dashes, are replaced by a look-alike and
+//! anything else by `REPLACEMENT`.
+//!
+//! This module is also compiled into the build script, so it only uses std.
+use std::borrow::Cow;
+
+/// Drawn for characters without a glyph or a look-alike.
+pub const REPLACEMENT: char = '?';
+
+/// Base letters of Latin Extended-A (U+0100 to U+017F), by code point.
+const LATIN_EXTENDED_A: &str = "AaAaAaCcCcCcCcDdDdEeEeEeEeEeGgGgGgGgHhHhIiIiIiIiIiIiJjKkkLlLlLlLlLlNnNnNnnNnOoOoOoOoRrRrRrSsSsSsSsTtTtTtUuUuUuUuUuUuWwYyYZzZzZzs";
+
+/// Whether the font has a glyph for `c`.
+pub fn in_font(c: char) -> bool {
+    matches!(c, ' '..='~' | '\u{a0}'..='\u{ff}')
+}
+
+/// What to draw instead of a character that isn't in the font.
+pub fn look_alike(c: char) -> Option<&'static str> {
+    let s = match c {
+        '\t' | '\u{2000}'..='\u{200a}' | '\u{202f}' => " ",
+        '\u{200b}' | '\u{2060}' | '\u{feff}' => "",
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' => "\"",
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2022}' => "\u{b7}",
+        '\u{2039}' => "<",
+        '\u{203a}' => ">",
+        '\u{20ac}' => "EUR",
+        '\u{2122}' => "TM",
+        '\u{152}' => "OE",
+        '\u{153}' => "oe",
+        '\u{100}'..='\u{17f}' => {
+            let i = c as usize - 0x100;
+            &LATIN_EXTENDED_A[i..i + 1]
+        }
+        _ => return None,
+    };
+    Some(s)
+}
+
+/// Whether `c` is drawn as itself or a look-alike.
+pub fn has_glyph(c: char) -> bool {
+    c == '\n' || in_font(c) || look_alike(c).is_some()
+}
+
+/// Replace the characters the font doesn't have.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if text.chars().all(|c| c == '\n' || in_font(c)) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\n' || in_font(c) {
+            out.push(c);
+        } else if let Some(s) = look_alike(c) {
+            out.push_str(s);
+        } else {
+            out.push(REPLACEMENT);
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_text() {
+        assert!(matches!(normalize("Crème brûlée"), Cow::Borrowed(_)));
+        assert_eq!(
+            normalize("“Don’t Panic” — Łódź…"),
+            "\"Don't Panic\" - Lódz..."
+        );
+        assert_eq!(normalize("Œuvre ☃"), "OEuvre ?");
+        assert!(!has_glyph('☃'));
+        assert!(has_glyph('ž'));
+    }
+}
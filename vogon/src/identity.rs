@@ -0,0 +1,124 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::protocol::content_hash;
+use crate::TOTAL_DEVICES;
+
+/// The id of a badge, always in `1..=TOTAL_DEVICES`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId(u8);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidDeviceId(pub String);
+
+impl fmt::Display for InvalidDeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid device id {:?}, expected 1..={}",
+            self.0, TOTAL_DEVICES
+        )
+    }
+}
+
+impl std::error::Error for InvalidDeviceId {}
+
+impl DeviceId {
+    pub fn new(id: u8) -> Result<Self, InvalidDeviceId> {
+        if (1..=TOTAL_DEVICES).contains(&id) {
+            Ok(DeviceId(id))
+        } else {
+            Err(InvalidDeviceId(id.to_string()))
+        }
+    }
+
+    /// Derive an id from a MAC address, for badges that were never given one.
+    ///
+    /// Different badges can end up with the same id, but it's stable across
+    /// reboots.
+    pub fn from_mac(mac: &[u8]) -> Self {
+        DeviceId((content_hash(mac) % TOTAL_DEVICES as u32) as u8 + 1)
+    }
+
+    /// Like `from_mac`, but skips the ids in `taken`. Returns `None` if all
+    /// ids are taken.
+    pub fn unused_from_mac(mac: &[u8], taken: &[u8]) -> Option<Self> {
+        let start = Self::from_mac(mac).0 - 1;
+        (0..TOTAL_DEVICES)
+            .map(|i| DeviceId((start + i) % TOTAL_DEVICES + 1))
+            .find(|id| !taken.contains(&id.0))
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = InvalidDeviceId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .map_err(|_| InvalidDeviceId(s.to_string()))
+            .and_then(DeviceId::new)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Name for a badge that was never given one.
+pub fn default_name(mac: &[u8]) -> String {
+    match mac {
+        [.., a, b] => format!("vogon-{:02x}{:02x}", a, b),
+        _ => "vogon".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_range() {
+        assert!(DeviceId::new(0).is_err());
+        assert_eq!(DeviceId::new(1).unwrap().get(), 1);
+        assert_eq!(DeviceId::new(TOTAL_DEVICES).unwrap().get(), TOTAL_DEVICES);
+        assert!(DeviceId::new(TOTAL_DEVICES + 1).is_err());
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!("7".parse(), Ok(DeviceId(7)));
+        assert_eq!(" 42\n".parse(), Ok(DeviceId(42)));
+        assert!("0".parse::<DeviceId>().is_err());
+        assert!("256".parse::<DeviceId>().is_err());
+        assert!("seven".parse::<DeviceId>().is_err());
+    }
+
+    #[test]
+    fn from_mac_is_in_range_and_stable() {
+        let mac = [0x24, 0x0a, 0xc4, 0x12, 0x34, 0x56];
+        let id = DeviceId::from_mac(&mac);
+        assert!((1..=TOTAL_DEVICES).contains(&id.get()));
+        assert_eq!(id, DeviceId::from_mac(&mac));
+        assert_eq!(default_name(&mac), "vogon-3456");
+    }
+
+    #[test]
+    fn unused_from_mac_skips_taken_ids() {
+        let mac = [0x24, 0x0a, 0xc4, 0x12, 0x34, 0x56];
+        let id = DeviceId::from_mac(&mac);
+        assert_eq!(DeviceId::unused_from_mac(&mac, &[]), Some(id));
+
+        let unused = DeviceId::unused_from_mac(&mac, &[id.get()]).unwrap();
+        assert_ne!(unused, id);
+
+        let all: Vec<u8> = (1..=TOTAL_DEVICES).collect();
+        assert_eq!(DeviceId::unused_from_mac(&mac, &all), None);
+    }
+}
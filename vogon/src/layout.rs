@@ -0,0 +1,242 @@
+//! How text is laid out on the screen.
+//!
+//! Text is wrapped by its measured width in pixels, so it works for any
+//! embedded-graphics font, proportional ones too. Words that are too long for
+//! the rest of a line are hyphenated.
+//!
+//! This module is also compiled into the build script to check the poems.
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::renderer::TextRenderer;
+use embedded_graphics::text::{Baseline, TextStyle};
+
+/// Longest poem in lines on the screen, including where it came from, its
+/// title and credit, so it doesn't scroll by for minutes.
+pub const MAX_POEM_LINES: usize = 64;
+
+/// Characters that stay on either side when a word is hyphenated, unless it
+/// doesn't fit on a line by itself.
+const MIN_HYPHEN_CHARS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub text: String,
+    /// Width in pixels.
+    pub width: u32,
+}
+
+/// Text wrapped into lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub lines: Vec<Line>,
+    /// Distance between the tops of two lines in pixels.
+    pub line_height: u32,
+}
+
+impl Layout {
+    /// Wrap `text` into lines of at most `max_width` pixels. Newlines in the
+    /// text are kept, and so is the indentation at the start of a line.
+    pub fn new<R: TextRenderer>(
+        renderer: &R,
+        style: &TextStyle,
+        text: &str,
+        max_width: u32,
+    ) -> Self {
+        let mut wrapper = Wrapper {
+            renderer,
+            max_width,
+            lines: vec![],
+            line: String::new(),
+        };
+        for paragraph in text.split('\n') {
+            let words = paragraph.trim_start();
+            wrapper.line = paragraph[..paragraph.len() - words.len()].to_string();
+            for word in words.split_whitespace() {
+                wrapper.push_word(word);
+            }
+            wrapper.end_line();
+        }
+        Layout {
+            lines: wrapper.lines,
+            line_height: style.line_height.to_absolute(renderer.line_height()),
+        }
+    }
+
+    /// Height of all lines in pixels.
+    pub fn height(&self) -> u32 {
+        self.lines.len() as u32 * self.line_height
+    }
+
+    /// Number of lines that fit on a screen of `height` pixels.
+    pub fn lines_per_screen(&self, height: u32) -> usize {
+        (height / self.line_height).max(1) as usize
+    }
+
+    /// Number of screens full of text it takes to show all lines.
+    pub fn screens(&self, height: u32) -> usize {
+        self.lines.len().div_ceil(self.lines_per_screen(height))
+    }
+
+    /// The lines separated by newlines.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+struct Wrapper<'a, R> {
+    renderer: &'a R,
+    max_width: u32,
+    lines: Vec<Line>,
+    /// The line that is being filled.
+    line: String,
+}
+
+impl<R: TextRenderer> Wrapper<'_, R> {
+    fn width(&self, s: &str) -> u32 {
+        let metrics = self
+            .renderer
+            .measure_string(s, Point::zero(), Baseline::Top);
+        metrics.next_position.x.max(0) as u32
+    }
+
+    fn fits(&self, s: &str) -> bool {
+        self.width(s) <= self.max_width
+    }
+
+    /// Whether the line has words, and not just indentation.
+    fn has_words(&self) -> bool {
+        !self.line.trim().is_empty()
+    }
+
+    fn end_line(&mut self) {
+        let text = std::mem::take(&mut self.line);
+        let width = self.width(&text);
+        self.lines.push(Line { text, width });
+    }
+
+    fn push_word(&mut self, word: &str) {
+        let separator = if self.has_words() { " " } else { "" };
+        let line = format!("{}{}{}", self.line, separator, word);
+        if self.fits(&line) {
+            self.line = line;
+            return;
+        }
+
+        // Fill up the line with the start of the word if it can be split
+        // nicely, otherwise start a new line
+        let prefix = format!("{}{}", self.line, separator);
+        let split = match self.hyphenate(&prefix, word, false) {
+            Some(split) => Some(split),
+            None if self.has_words() => {
+                self.end_line();
+                return self.push_word(word);
+            }
+            None => self.hyphenate(&prefix, word, true),
+        };
+        match split {
+            Some((head, tail)) => {
+                self.line = head;
+                self.end_line();
+                self.push_word(tail);
+            }
+            None => {
+                // Not even one character fits
+                self.line = prefix + word;
+                self.end_line();
+            }
+        }
+    }
+
+    /// Split `word` so that `prefix`, the start of the word and a hyphen fit
+    /// on a line, returning that line and the rest of the word.
+    ///
+    /// Words are split after a hyphen or between two consonants that have a
+    /// vowel on either side, like "gob-berwarts". When `force` is set, a word
+    /// is split anywhere, which is needed for words wider than a line.
+    fn hyphenate<'w>(&self, prefix: &str, word: &'w str, force: bool) -> Option<(String, &'w str)> {
+        let chars: Vec<(usize, char)> = word.char_indices().collect();
+        let min = if force { 1 } else { MIN_HYPHEN_CHARS };
+        (min..=chars.len().saturating_sub(min))
+            .rev()
+            .filter(|i| force || is_break(word, &chars, *i))
+            .map(|i| {
+                let (head, tail) = word.split_at(chars[i].0);
+                let hyphen = if head.ends_with('-') { "" } else { "-" };
+                (format!("{}{}{}", prefix, head, hyphen), tail)
+            })
+            .find(|(line, _)| self.fits(line))
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiouyàáâãäåæèéêëìíîïòóôõöøùúûüý".contains(c.to_lowercase().next().unwrap())
+}
+
+fn is_consonant(c: char) -> bool {
+    c.is_alphabetic() && !is_vowel(c)
+}
+
+/// Whether a word can be hyphenated before the `i`th character.
+fn is_break(word: &str, chars: &[(usize, char)], i: usize) -> bool {
+    let (before, after) = (chars[i - 1].1, chars[i].1);
+    if before == '-' {
+        return true;
+    }
+    let (head, tail) = word.split_at(chars[i].0);
+    is_consonant(before)
+        && is_consonant(after)
+        && head.chars().any(is_vowel)
+        && tail.chars().any(is_vowel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::{character_style, text_style};
+
+    fn wrap(text: &str, max_width: u32) -> Vec<String> {
+        Layout::new(&character_style(), &text_style(), text, max_width)
+            .lines
+            .into_iter()
+            .map(|line| line.text)
+            .collect()
+    }
+
+    #[test]
+    fn wraps_by_width() {
+        // FONT_5X7 advances 5 pixels per character, so 25 fit on the screen
+        assert_eq!(
+            wrap("Oh freddled gruntbuggly, thy micturations are to me", 128),
+            vec!["Oh freddled gruntbuggly,", "thy micturations are to", "me"]
+        );
+        assert_eq!(
+            wrap("  Groop\n\nI implore thee", 128),
+            vec!["  Groop", "", "I implore thee"]
+        );
+    }
+
+    #[test]
+    fn hyphenates() {
+        assert_eq!(
+            wrap("Oh freddled gruntbuggly", 100),
+            vec!["Oh freddled grunt-", "buggly"]
+        );
+        assert_eq!(wrap("gobberwarts", 50), vec!["gobber-", "warts"]);
+        assert_eq!(wrap("one-eyed fawn", 35), vec!["one-", "eyed", "fawn"]);
+        // No vowels, so no good place to split it
+        assert_eq!(wrap("Grrrrrrrrrr", 25), vec!["Grrr-", "rrrr-", "rrr"]);
+    }
+
+    #[test]
+    fn metrics() {
+        let layout = Layout::new(&character_style(), &text_style(), "a\nb\nc\nd\ne", 128);
+        assert_eq!(layout.line_height, 7);
+        assert_eq!(layout.height(), 35);
+        assert_eq!(layout.lines_per_screen(32), 4);
+        assert_eq!(layout.screens(32), 2);
+        assert_eq!(layout.lines[0].width, 5);
+    }
+}
@@ -0,0 +1,30 @@
+//! The parts of the Vogon Poetry Transceiver that don't depend on ESP-IDF.
+//!
+//! Keeping them in a separate crate means they can be built and tested on the
+//! host with `cargo test` from this directory.
+pub mod animation;
+pub mod assets;
+pub mod battery;
+pub mod corpus;
+pub mod effects;
+pub mod font;
+pub mod fragments;
+pub mod glyphs;
+pub mod identity;
+pub mod layout;
+pub mod library;
+pub mod mesh;
+pub mod peers;
+pub mod playback;
+pub mod power;
+pub mod protection;
+pub mod protocol;
+pub mod relay;
+pub mod screen;
+pub mod selector;
+pub mod shell;
+pub mod text;
+pub mod transceiver;
+pub mod utils;
+
+pub const TOTAL_DEVICES: u8 = 42;
@@ -0,0 +1,283 @@
+//! The poems a badge knows, optionally kept as files in a directory so they
+//! survive a reboot.
+//!
+//! Every poem is stored as `<id>.txt`, and the ids of the built-in poems the
+//! library has had in `builtin`. On the badge the directory is on a SPIFFS
+//! partition, mounted through the VFS so `std::fs` works on it.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+use rand::Rng;
+
+/// File with the ids of the built-in poems that were added to a library,
+/// so the ones that were removed aren't added again.
+const BUILTIN_FILE: &str = "builtin";
+
+/// Poems by id. Ids stay the same when other poems are added or removed.
+pub struct Library {
+    poems: BTreeMap<u8, String>,
+    /// Ids of the built-in poems. They are never given to added poems, so an
+    /// id means the same poem on every badge.
+    reserved: BTreeSet<u8>,
+    dir: Option<PathBuf>,
+}
+
+impl Library {
+    /// A library that only lives in memory.
+    pub fn new(poems: Vec<(u8, String)>) -> Self {
+        Library {
+            reserved: poems.iter().map(|(id, _)| *id).collect(),
+            poems: poems.into_iter().collect(),
+            dir: None,
+        }
+    }
+
+    /// Load the library from `dir` and bring the `builtin` poems up to date:
+    /// the ones it didn't have yet are added, as on first boot or after a
+    /// firmware update, and the ones that were removed stay removed. A poem
+    /// that was added under an id that is now a built-in one gets a new id.
+    pub fn open(dir: impl Into<PathBuf>, builtin: Vec<(u8, String)>) -> Result<Self> {
+        let dir = dir.into();
+        let mut stored = BTreeMap::new();
+        let mut added = None;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            if name == Some(BUILTIN_FILE) {
+                let ids = fs::read_to_string(&path)?;
+                added = Some(
+                    ids.split_whitespace()
+                        .filter_map(|id| id.parse::<u8>().ok())
+                        .collect::<BTreeSet<u8>>(),
+                );
+                continue;
+            }
+            let id = name
+                .and_then(|name| name.strip_suffix(".txt"))
+                .and_then(|id| id.parse::<u8>().ok());
+            match id {
+                Some(id) => {
+                    stored.insert(id, fs::read_to_string(&path)?);
+                }
+                None => log::warn!("Ignoring {} in the poem library", path.display()),
+            }
+        }
+        log::info!("Loaded {} poems from the library", stored.len());
+
+        let mut library = Library::new(builtin);
+        library.dir = Some(dir);
+        let mut moved = vec![];
+        for (id, text) in stored {
+            let builtin = match library.poems.get(&id) {
+                Some(builtin) => builtin,
+                None => {
+                    library.poems.insert(id, text);
+                    continue;
+                }
+            };
+            // Libraries from before `BUILTIN_FILE` only tell by the text
+            let was_builtin = match &added {
+                Some(added) => added.contains(&id),
+                None => text == *builtin,
+            };
+            if text != *builtin {
+                if was_builtin {
+                    log::info!("Updating built-in poem {}", id);
+                } else {
+                    moved.push(text);
+                }
+                fs::write(library.path(id).unwrap(), builtin)?;
+            }
+        }
+        for id in library.reserved.clone() {
+            let path = library.path(id).unwrap();
+            if path.exists() {
+                continue;
+            }
+            if added.as_ref().is_some_and(|added| added.contains(&id)) {
+                library.poems.remove(&id);
+            } else {
+                log::info!("Adding built-in poem {}", id);
+                fs::write(path, &library.poems[&id])?;
+            }
+        }
+        for text in moved {
+            let id = library.add(text)?;
+            log::warn!(
+                "Moved an added poem to id {}, its id is now a built-in poem",
+                id
+            );
+        }
+
+        let ids: Vec<String> = library.reserved.iter().map(u8::to_string).collect();
+        fs::write(
+            library.dir.as_ref().unwrap().join(BUILTIN_FILE),
+            ids.join(" "),
+        )?;
+        Ok(library)
+    }
+
+    fn path(&self, id: u8) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.txt", id)))
+    }
+
+    pub fn get(&self, id: u8) -> Option<&str> {
+        self.poems.get(&id).map(String::as_str)
+    }
+
+    /// Whether `id` is one of the poems the library was created with, which
+    /// has the same id on every badge. Other poems have to be sent as text.
+    pub fn is_shared(&self, id: u8) -> bool {
+        self.reserved.contains(&id)
+    }
+
+    /// Id of a poem with exactly this text.
+    pub fn find(&self, text: &str) -> Option<u8> {
+        self.iter().find(|(_, t)| *t == text).map(|(id, _)| id)
+    }
+
+    /// Add a poem under the highest id that isn't used or reserved. Built-in
+    /// poems are numbered from 0, so a firmware update with more of them is
+    /// unlikely to need the id.
+    pub fn add(&mut self, text: String) -> Result<u8> {
+        let id = (0..=u8::MAX)
+            .rev()
+            .find(|id| !self.poems.contains_key(id) && !self.reserved.contains(id))
+            .ok_or_else(|| anyhow!("The library is full"))?;
+        if let Some(path) = self.path(id) {
+            fs::write(path, &text)?;
+        }
+        self.poems.insert(id, text);
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: u8) -> Result<()> {
+        if !self.poems.contains_key(&id) {
+            bail!("No poem {}", id);
+        }
+        if let Some(path) = self.path(id) {
+            fs::remove_file(path)?;
+        }
+        self.poems.remove(&id);
+        Ok(())
+    }
+
+    /// Pick a poem at random.
+    pub fn random<R: Rng>(&self, rng: &mut R) -> Option<u8> {
+        if self.poems.is_empty() {
+            return None;
+        }
+        self.poems
+            .keys()
+            .nth(rng.gen_range(0..self.poems.len()))
+            .copied()
+    }
+
+    /// Poems by id.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &str)> {
+        self.poems.iter().map(|(id, text)| (*id, text.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.poems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.poems.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poems() -> Vec<(u8, String)> {
+        vec![(0, "first".to_string()), (1, "second".to_string())]
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vogon-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        let mut library = Library::new(poems());
+        assert_eq!(library.add("third".to_string()).unwrap(), 255);
+        assert!(library.is_shared(1));
+        assert!(!library.is_shared(255));
+        library.remove(0).unwrap();
+        assert!(library.remove(0).is_err());
+        assert_eq!(library.get(1), Some("second"));
+        assert_eq!(library.find("third"), Some(255));
+        // The id of a removed built-in poem isn't reused
+        assert_eq!(library.add("fourth".to_string()).unwrap(), 254);
+        library.remove(255).unwrap();
+        assert_eq!(library.add("fifth".to_string()).unwrap(), 255);
+        assert_eq!(library.len(), 3);
+    }
+
+    #[test]
+    fn persists_in_a_directory() {
+        let dir = temp_dir("library");
+        let mut library = Library::open(&dir, poems()).unwrap();
+        assert_eq!(library.len(), 2);
+        library.remove(0).unwrap();
+        library.add("third".to_string()).unwrap();
+        library.add("fourth".to_string()).unwrap();
+
+        // Removed built-in poems aren't added again
+        let library = Library::open(&dir, poems()).unwrap();
+        let poems: Vec<(u8, &str)> = library.iter().collect();
+        assert_eq!(poems, vec![(1, "second"), (254, "fourth"), (255, "third")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn updates_the_builtin_poems() {
+        let dir = temp_dir("update");
+        let mut library = Library::open(&dir, poems()).unwrap();
+        library.add("mine".to_string()).unwrap();
+        // Added by firmware that numbered added poems from the bottom
+        fs::write(dir.join("2.txt"), "older").unwrap();
+
+        let mut builtin = poems();
+        builtin.push((2, "third".to_string()));
+        builtin.push((3, "fourth".to_string()));
+        let library = Library::open(&dir, builtin.clone()).unwrap();
+        let poems: Vec<(u8, &str)> = library.iter().collect();
+        assert_eq!(
+            poems,
+            vec![
+                (0, "first"),
+                (1, "second"),
+                (2, "third"),
+                (3, "fourth"),
+                (254, "older"),
+                (255, "mine")
+            ]
+        );
+        assert!(library.is_shared(2));
+        assert!(!library.is_shared(254));
+        assert_eq!(fs::read_to_string(dir.join("2.txt")).unwrap(), "third");
+
+        // The same poems after a reboot
+        let reopened = Library::open(&dir, builtin).unwrap();
+        assert!(reopened.iter().eq(library.iter()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn random_picks_existing_poems() {
+        let mut library = Library::new(poems());
+        library.remove(0).unwrap();
+        let rng = &mut rand::thread_rng();
+        assert!((0..10).all(|_| library.random(rng) == Some(1)));
+        library.remove(1).unwrap();
+        assert_eq!(library.random(rng), None);
+    }
+}
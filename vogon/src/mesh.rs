@@ -0,0 +1,640 @@
+//! Simulation of a whole parade of badges in virtual time.
+//!
+//! Every badge runs the real send, receive and display logic from
+//! `transceiver`, but frames travel over a simulated broadcast medium and the
+//! display is a `HeadlessScreen`, so an hour of parade takes seconds.
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+use embedded_graphics::prelude::Point;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::corpus;
+use crate::identity::DeviceId;
+use crate::library::Library;
+use crate::peers::{Mac, DEFAULT_PEER_TIMEOUT};
+use crate::playback::Policy;
+use crate::protocol::{Message, Packet};
+use crate::relay::RELAY_DELAY_RANGE;
+use crate::screen::HeadlessScreen;
+use crate::selector::Strategy;
+use crate::transceiver::{self, Badge, Broadcaster, Collision, DisplayLoop, PoemText, Scene, Step};
+use crate::TOTAL_DEVICES;
+
+pub struct MeshConfig {
+    pub devices: u8,
+    /// Probability that a frame doesn't make it to a badge in range.
+    pub packet_loss: f64,
+    /// Delivery latency, picked uniformly from this range.
+    pub latency: Range<Duration>,
+    /// Distance between neighbouring badges in the parade, in meters.
+    pub spacing: f32,
+    /// How far a broadcast reaches, in meters.
+    pub range: f32,
+    /// Badges are switched on at a random time within this window.
+    pub boot_window: Duration,
+    /// Badges forget peers that weren't heard from for this long.
+    pub peer_timeout: Duration,
+    /// Device ids of the badges, `1..=devices` if not set.
+    pub ids: Option<Vec<DeviceId>>,
+    /// Whether badges switch ids when they find another badge using theirs.
+    pub auto_resolve: bool,
+    /// Relay poems with this TTL, see `Badge::relay_ttl`.
+    pub relay_ttl: Option<u8>,
+    /// Broadcast the text of poems instead of their id.
+    pub send_text: bool,
+    /// How badges queue the poems they receive.
+    pub playback: Policy,
+    /// How badges pick the poems they broadcast.
+    pub selection: Strategy,
+    pub seed: u64,
+}
+
+impl Default for MeshConfig {
+    fn default() -> Self {
+        MeshConfig {
+            devices: TOTAL_DEVICES,
+            packet_loss: 0.1,
+            latency: Duration::from_millis(1)..Duration::from_millis(20),
+            spacing: 10.0,
+            range: 100.0,
+            boot_window: Duration::from_secs(10),
+            peer_timeout: DEFAULT_PEER_TIMEOUT,
+            ids: None,
+            auto_resolve: false,
+            relay_ttl: None,
+            send_text: false,
+            playback: Policy::default(),
+            selection: Strategy::default(),
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceReport {
+    pub device_id: u8,
+    pub sent: u32,
+    pub received: u32,
+    pub dropped: u32,
+    /// Received poems that made it to the display.
+    pub shown: u32,
+    /// Received poems that weren't shown, because they were repeats or too
+    /// many were queued.
+    pub skipped: u32,
+    /// Times the badge fell back to "Random poem:".
+    pub random: u32,
+    /// Badges nearby at the end of the run.
+    pub nearby: usize,
+    /// Times the badge heard a device id from two MAC addresses.
+    pub collisions: u32,
+    pub relayed: u32,
+    /// Badges whose poems were received, directly or relayed.
+    pub origins: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub elapsed: Duration,
+    pub devices: Vec<DeviceReport>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "After {} minutes:", self.elapsed.as_secs() / 60)?;
+        writeln!(
+            f,
+            "{:>6} {:>6} {:>9} {:>8} {:>6} {:>8} {:>7} {:>8} {:>7} {:>11} {:>8} {:>8}",
+            "device",
+            "sent",
+            "received",
+            "dropped",
+            "shown",
+            "skipped",
+            "random",
+            "random%",
+            "nearby",
+            "collisions",
+            "relayed",
+            "origins"
+        )?;
+        for d in &self.devices {
+            let displayed = d.shown + d.random;
+            writeln!(
+                f,
+                "{:>6} {:>6} {:>9} {:>8} {:>6} {:>8} {:>7} {:>7.1}% {:>7} {:>11} {:>8} {:>8}",
+                d.device_id,
+                d.sent,
+                d.received,
+                d.dropped,
+                d.shown,
+                d.skipped,
+                d.random,
+                if displayed > 0 {
+                    d.random as f32 * 100.0 / displayed as f32
+                } else {
+                    0.0
+                },
+                d.nearby,
+                d.collisions,
+                d.relayed,
+                d.origins
+            )?;
+        }
+        Ok(())
+    }
+}
+
+enum Event {
+    Boot(usize),
+    Send(usize),
+    /// A frame from a MAC address, received with an RSSI.
+    Deliver(usize, Mac, i8, Vec<u8>),
+    /// Step the display loop, unless a later wake up superseded this one.
+    Wake(usize, u64),
+    /// Switch to a new device id after a collision.
+    Reboot(usize, DeviceId),
+    Relay(usize, Message),
+}
+
+struct Scheduled {
+    at: Duration,
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    // Reversed, so the `BinaryHeap` pops the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at).then(other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    events: BinaryHeap<Scheduled>,
+    seq: u64,
+}
+
+impl Queue {
+    fn push(&mut self, at: Duration, event: Event) {
+        self.seq += 1;
+        self.events.push(Scheduled {
+            at,
+            seq: self.seq,
+            event,
+        });
+    }
+}
+
+struct Device {
+    badge: Badge,
+    position: f32,
+    broadcaster: Broadcaster,
+    display_loop: DisplayLoop,
+    /// Whether the display loop is waiting for a poem.
+    listening: bool,
+    generation: u64,
+    shown: u32,
+    /// Poems skipped before the last reboot.
+    skipped: u32,
+    random: u32,
+    origins: BTreeSet<u8>,
+}
+
+pub struct Mesh {
+    config: MeshConfig,
+    poems: Vec<(u8, String)>,
+    devices: Vec<Device>,
+    queue: Queue,
+    rng: StdRng,
+    now: Duration,
+}
+
+impl Mesh {
+    /// Panics if `config.devices` is more than `TOTAL_DEVICES`, or if
+    /// `config.ids` doesn't have an id for every device.
+    pub fn new(config: MeshConfig) -> Self {
+        let poems = corpus::poems();
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut queue = Queue::default();
+        let devices = (0..config.devices)
+            .map(|i| {
+                let boot_at = rng.gen_range(Duration::ZERO..=config.boot_window);
+                queue.push(boot_at, Event::Boot(i as usize));
+                let id = match &config.ids {
+                    Some(ids) => ids[i as usize],
+                    None => DeviceId::new(i + 1).unwrap(),
+                };
+                Device {
+                    badge: badge(&config, id, [0x24, 0x0a, 0xc4, 0x00, 0x00, i], &poems),
+                    position: i as f32 * config.spacing,
+                    broadcaster: Broadcaster::new(id, config.selection.clone()),
+                    display_loop: DisplayLoop::new(id, config.playback.clone(), boot_at),
+                    listening: false,
+                    generation: 0,
+                    shown: 0,
+                    skipped: 0,
+                    random: 0,
+                    origins: BTreeSet::new(),
+                }
+            })
+            .collect();
+
+        Mesh {
+            config,
+            poems,
+            devices,
+            queue,
+            rng,
+            now: Duration::ZERO,
+        }
+    }
+
+    /// Advance the simulation by `duration` of virtual time.
+    pub fn run_for(&mut self, duration: Duration) {
+        let until = self.now + duration;
+        while self.queue.events.peek().is_some_and(|e| e.at <= until) {
+            let Scheduled { at, event, .. } = self.queue.events.pop().unwrap();
+            self.now = at;
+            match event {
+                Event::Boot(i) => self.boot(i),
+                Event::Send(i) => self.send(i),
+                Event::Deliver(i, mac, rssi, frame) => self.deliver(i, mac, rssi, &frame),
+                Event::Wake(i, generation) => {
+                    if generation == self.devices[i].generation {
+                        self.wake(i);
+                    }
+                }
+                Event::Reboot(i, id) => self.reboot(i, id),
+                Event::Relay(i, message) => {
+                    let packet = self.devices[i].broadcaster.message(message);
+                    self.devices[i].badge.counters.lock().unwrap().relayed += 1;
+                    self.broadcast(i, &packet);
+                }
+            }
+        }
+        self.now = until;
+    }
+
+    pub fn report(&self) -> Report {
+        Report {
+            elapsed: self.now,
+            devices: self
+                .devices
+                .iter()
+                .map(|d| {
+                    let c = d.badge.counters.lock().unwrap();
+                    DeviceReport {
+                        device_id: d.badge.device_id.get(),
+                        sent: c.sent,
+                        received: c.received,
+                        dropped: c.dropped,
+                        shown: d.shown,
+                        skipped: d.skipped + d.display_loop.skipped(),
+                        random: d.random,
+                        nearby: d.badge.peers.lock().unwrap().nearby(self.now),
+                        collisions: c.collisions,
+                        relayed: c.relayed,
+                        origins: d.origins.len(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn boot(&mut self, i: usize) {
+        self.boot_display(i);
+        let device = &self.devices[i];
+        self.queue.push(
+            self.now + device.broadcaster.delay(&mut self.rng),
+            Event::Send(i),
+        );
+    }
+
+    fn boot_display(&mut self, i: usize) {
+        let mut screen = HeadlessScreen::default();
+        transceiver::boot(&mut screen).unwrap();
+        let booted = self.now + screen.elapsed;
+        let device = &mut self.devices[i];
+        device.display_loop =
+            DisplayLoop::new(device.badge.device_id, self.config.playback.clone(), booted);
+        self.queue.push(booted, Event::Wake(i, device.generation));
+    }
+
+    /// Store a new device id and reboot, like the firmware does. The counters
+    /// are kept for the report.
+    fn reboot(&mut self, i: usize, id: DeviceId) {
+        let device = &mut self.devices[i];
+        let badge = badge(&self.config, id, device.badge.mac, &self.poems);
+        *badge.counters.lock().unwrap() =
+            std::mem::take(&mut device.badge.counters.lock().unwrap());
+        device.badge = badge;
+        device.broadcaster = Broadcaster::new(id, self.config.selection.clone());
+        device.skipped += device.display_loop.skipped();
+        device.listening = false;
+        device.generation += 1;
+        self.boot_display(i);
+    }
+
+    fn send(&mut self, i: usize) {
+        let device = &mut self.devices[i];
+        let library = device.badge.library.lock().unwrap();
+        let packets = if self.config.send_text {
+            let id = device
+                .broadcaster
+                .next_poem(&mut self.rng, &library)
+                .unwrap();
+            device
+                .broadcaster
+                .text_packets(library.get(id).unwrap())
+                .unwrap()
+        } else {
+            device
+                .broadcaster
+                .next_packets(&mut self.rng, &library)
+                .unwrap()
+        };
+        drop(library);
+        device.badge.counters.lock().unwrap().sent += 1;
+        self.queue.push(
+            self.now + device.broadcaster.delay(&mut self.rng),
+            Event::Send(i),
+        );
+        for packet in packets {
+            self.broadcast(i, &packet);
+        }
+    }
+
+    /// Deliver a packet to the badges in range of badge `i`.
+    fn broadcast(&mut self, i: usize, packet: &Packet) {
+        let frame = packet.serialize();
+        let device = &self.devices[i];
+        let (mac, position) = (device.badge.mac, device.position);
+        for (j, other) in self.devices.iter().enumerate() {
+            let distance = (other.position - position).abs();
+            if j == i || distance > self.config.range || self.rng.gen_bool(self.config.packet_loss)
+            {
+                continue;
+            }
+            let latency = self.rng.gen_range(self.config.latency.clone());
+            self.queue.push(
+                self.now + latency,
+                Event::Deliver(j, mac, rssi(distance), frame.clone()),
+            );
+        }
+    }
+
+    fn deliver(&mut self, i: usize, mac: Mac, rssi: i8, frame: &[u8]) {
+        let device = &mut self.devices[i];
+        if let Some(poem) = device.badge.receive(mac, Some(rssi), frame, self.now) {
+            if let Some(message) = device.badge.relay(&poem) {
+                let delay = Duration::from_millis(self.rng.gen_range(RELAY_DELAY_RANGE));
+                self.queue.push(self.now + delay, Event::Relay(i, message));
+            }
+            device.origins.insert(poem.src);
+            if let PoemText::Library(id) = poem.text {
+                device.broadcaster.seen(id);
+            }
+            let poem = device.badge.store(poem);
+            device.display_loop.receive(self.now, poem);
+            // Wake up a display loop that is waiting for a poem
+            if device.listening {
+                device.generation += 1;
+                self.queue.push(self.now, Event::Wake(i, device.generation));
+            }
+        }
+    }
+
+    fn wake(&mut self, i: usize) {
+        let device = &mut self.devices[i];
+        if let Some(collision) = device.badge.take_collision() {
+            device.display_loop.warn(collision);
+        }
+        device.listening = false;
+        loop {
+            let step = device.display_loop.step(
+                self.now,
+                &device.badge.library.lock().unwrap(),
+                &mut self.rng,
+            );
+            match step {
+                Step::Show(scene) => {
+                    match scene {
+                        Scene::Received(_) => device.shown += 1,
+                        Scene::Random(_) => device.random += 1,
+                        _ => {}
+                    }
+                    // Poems are delivered while the scene is shown, but all
+                    // at once afterwards, so random poems aren't interrupted
+                    let mut screen = HeadlessScreen::default();
+                    let queued = device.display_loop.queued();
+                    scene
+                        .render(
+                            &mut screen,
+                            &device.badge,
+                            self.now,
+                            queued,
+                            Point::zero(),
+                            || false,
+                        )
+                        .unwrap();
+                    let event = match scene {
+                        Scene::Collision(Collision {
+                            renumber: Some(id), ..
+                        }) => Event::Reboot(i, id),
+                        _ => Event::Wake(i, device.generation),
+                    };
+                    self.queue.push(self.now + screen.elapsed, event);
+                    return;
+                }
+                Step::Listen(timeout) => {
+                    // Poems that arrived while the display was busy are
+                    // already queued
+                    if device.display_loop.queued() > 0 {
+                        continue;
+                    }
+                    device.listening = true;
+                    self.queue
+                        .push(self.now + timeout, Event::Wake(i, device.generation));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn badge(config: &MeshConfig, id: DeviceId, mac: Mac, poems: &[(u8, String)]) -> Badge {
+    let mut badge = Badge::new(id, mac, Library::new(poems.to_vec()), config.peer_timeout);
+    badge.auto_resolve = config.auto_resolve;
+    badge.relay_ttl = config.relay_ttl;
+    badge
+}
+
+/// Signal strength at `distance` meters, using free space path loss.
+fn rssi(distance: f32) -> i8 {
+    (-40.0 - 20.0 * distance.max(1.0).log10()) as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn an_hour_of_parade() {
+        let mut mesh = Mesh::new(MeshConfig::default());
+        mesh.run_for(HOUR);
+        let report = mesh.report();
+
+        assert_eq!(report.devices.len(), TOTAL_DEVICES as usize);
+        let sent: u32 = report.devices.iter().map(|d| d.sent).sum();
+        let received: u32 = report.devices.iter().map(|d| d.received).sum();
+        // 5..20 seconds between broadcasts
+        for d in &report.devices {
+            assert!((180..=720).contains(&d.sent), "{:?}", d);
+            assert!(d.shown > 0, "{:?}", d);
+            assert_eq!(d.dropped, 0);
+            assert!((1..=20).contains(&d.nearby), "{:?}", d);
+            // Poems arrive faster than they can be shown, only the last few
+            // wait in the queue
+            assert!(
+                d.skipped > 0 && d.shown + d.skipped <= d.received,
+                "{:?}",
+                d
+            );
+        }
+        // Every badge hears at most the 20 badges within 100m
+        assert!(received > 0 && received < sent * 20);
+    }
+
+    #[test]
+    fn total_packet_loss_falls_back_to_random() {
+        let mut mesh = Mesh::new(MeshConfig {
+            devices: 5,
+            packet_loss: 1.0,
+            ..Default::default()
+        });
+        mesh.run_for(HOUR / 6);
+        for d in mesh.report().devices {
+            assert_eq!(d.received, 0);
+            assert_eq!(d.shown, 0);
+            assert!(d.random > 0);
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut mesh = Mesh::new(MeshConfig {
+            devices: 3,
+            spacing: 200.0,
+            ..Default::default()
+        });
+        mesh.run_for(HOUR / 6);
+        assert!(mesh
+            .report()
+            .devices
+            .iter()
+            .all(|d| d.received == 0 && d.nearby == 0));
+    }
+
+    #[test]
+    fn id_collisions() {
+        let ids: Vec<DeviceId> = [1, 2, 3, 3, 5]
+            .into_iter()
+            .map(|id| DeviceId::new(id).unwrap())
+            .collect();
+        let run = |auto_resolve| {
+            let mut mesh = Mesh::new(MeshConfig {
+                devices: 5,
+                ids: Some(ids.clone()),
+                auto_resolve,
+                ..Default::default()
+            });
+            mesh.run_for(HOUR / 6);
+            mesh.report().devices
+        };
+
+        // Everybody notices, but nobody changes ids
+        let devices = run(false);
+        assert!(devices.iter().all(|d| d.collisions > 0));
+        assert_eq!(devices[2].device_id, devices[3].device_id);
+
+        // The badge with the highest MAC address switches to an unused id
+        let devices = run(true);
+        assert_eq!(devices[2].device_id, 3);
+        let mut ids: Vec<u8> = devices.iter().map(|d| d.device_id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+    }
+
+    #[test]
+    fn relaying_reaches_further() {
+        let run = |relay_ttl| {
+            let mut mesh = Mesh::new(MeshConfig {
+                devices: 20,
+                range: 25.0,
+                relay_ttl,
+                ..Default::default()
+            });
+            mesh.run_for(HOUR / 6);
+            mesh.report().devices
+        };
+
+        // Only the two neighbours on either side are in range
+        let devices = run(None);
+        assert!(devices.iter().all(|d| d.origins <= 4 && d.relayed == 0));
+
+        // Every hop adds two badges on either side
+        let devices = run(Some(2));
+        assert!(devices.iter().all(|d| d.origins <= 12 && d.relayed > 0));
+        assert!(devices.iter().any(|d| d.origins > 4));
+    }
+
+    #[test]
+    fn text_poems() {
+        let mut mesh = Mesh::new(MeshConfig {
+            devices: 10,
+            send_text: true,
+            ..Default::default()
+        });
+        mesh.run_for(HOUR / 6);
+        for d in mesh.report().devices {
+            assert!(d.shown > 0, "{:?}", d);
+            assert_eq!(d.dropped, 0);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = || {
+            let mut mesh = Mesh::new(MeshConfig {
+                devices: 10,
+                ..Default::default()
+            });
+            mesh.run_for(HOUR / 6);
+            mesh.report()
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,183 @@
+//! Registry of the badges that were heard recently.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::protocol::{Message, Packet};
+
+pub type Mac = [u8; 6];
+
+/// Forget peers that weren't heard from for this long.
+pub const DEFAULT_PEER_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+    pub first_seen: Duration,
+    pub last_seen: Duration,
+    /// Valid packets received from the peer.
+    pub packets: u32,
+    pub poems: u32,
+    /// Signal strength of the last packet, in dBm.
+    pub rssi: Option<i8>,
+    /// Exponential moving average of the signal strength, in dBm.
+    pub rssi_avg: Option<f32>,
+    /// Battery charge in percent from the last status beacon.
+    pub battery: Option<u8>,
+}
+
+/// Peers keyed by MAC address and the device id they advertise.
+pub struct Peers {
+    peers: BTreeMap<(Mac, u8), Peer>,
+    timeout: Duration,
+}
+
+impl Default for Peers {
+    fn default() -> Self {
+        Peers::new(DEFAULT_PEER_TIMEOUT)
+    }
+}
+
+impl Peers {
+    pub fn new(timeout: Duration) -> Self {
+        Peers {
+            peers: BTreeMap::new(),
+            timeout,
+        }
+    }
+
+    /// Record a packet received from `mac`, returning whether it's a peer
+    /// that wasn't known yet.
+    pub fn seen(&mut self, mac: Mac, packet: &Packet, rssi: Option<i8>, now: Duration) -> bool {
+        self.expire(now);
+        let new = !self.peers.contains_key(&(mac, packet.src));
+        let peer = self.peers.entry((mac, packet.src)).or_insert(Peer {
+            first_seen: now,
+            last_seen: now,
+            packets: 0,
+            poems: 0,
+            rssi: None,
+            rssi_avg: None,
+            battery: None,
+        });
+        peer.last_seen = now;
+        peer.packets += 1;
+        match packet.message {
+            Message::Poem { .. } | Message::Relay { .. } => peer.poems += 1,
+            Message::Fragment { index, .. } => {
+                if index == 0 {
+                    peer.poems += 1;
+                }
+            }
+            Message::Status { battery } => peer.battery = battery,
+        }
+        if let Some(rssi) = rssi {
+            peer.rssi = Some(rssi);
+            peer.rssi_avg = Some(match peer.rssi_avg {
+                Some(avg) => avg * 0.8 + rssi as f32 * 0.2,
+                None => rssi as f32,
+            });
+        }
+        new
+    }
+
+    /// A MAC address other than `mac` that advertises `device_id`.
+    pub fn other_mac(&self, mac: Mac, device_id: u8) -> Option<Mac> {
+        self.peers
+            .keys()
+            .find(|(m, id)| *m != mac && *id == device_id)
+            .map(|(m, _)| *m)
+    }
+
+    /// Forget peers that weren't heard from within the timeout.
+    pub fn expire(&mut self, now: Duration) {
+        let timeout = self.timeout;
+        self.peers
+            .retain(|_, peer| now.saturating_sub(peer.last_seen) <= timeout);
+    }
+
+    /// Number of badges heard within the timeout.
+    pub fn nearby(&self, now: Duration) -> usize {
+        self.iter()
+            .filter(|(_, peer)| now.saturating_sub(peer.last_seen) <= self.timeout)
+            .count()
+    }
+
+    /// Peers by MAC address and advertised device id.
+    pub fn iter(&self) -> impl Iterator<Item = (&(Mac, u8), &Peer)> {
+        self.peers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Mac = [0xa; 6];
+    const B: Mac = [0xb; 6];
+
+    fn poem(src: u8) -> Packet {
+        Packet::new(src, 0, Message::Poem { id: 1 })
+    }
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn tracks_peers() {
+        let mut peers = Peers::default();
+        peers.seen(A, &poem(1), Some(-50), secs(1));
+        peers.seen(A, &poem(1), Some(-60), secs(5));
+        peers.seen(B, &poem(2), None, secs(6));
+
+        assert_eq!(peers.len(), 2);
+        let (key, a) = peers.iter().next().unwrap();
+        assert_eq!(*key, (A, 1));
+        assert_eq!(a.first_seen, secs(1));
+        assert_eq!(a.last_seen, secs(5));
+        assert_eq!(a.packets, 2);
+        assert_eq!(a.poems, 2);
+        assert_eq!(a.rssi, Some(-60));
+        assert_eq!(a.rssi_avg, Some(-52.0));
+        assert_eq!(a.battery, None);
+
+        let status = Packet::new(2, 1, Message::Status { battery: Some(40) });
+        peers.seen(B, &status, None, secs(7));
+        let (_, b) = peers.iter().nth(1).unwrap();
+        assert_eq!((b.packets, b.poems, b.battery), (2, 1, Some(40)));
+    }
+
+    #[test]
+    fn finds_other_macs_with_the_same_id() {
+        let mut peers = Peers::default();
+        assert!(peers.seen(A, &poem(1), None, secs(0)));
+        assert!(!peers.seen(A, &poem(1), None, secs(1)));
+        assert_eq!(peers.other_mac(A, 1), None);
+
+        assert!(peers.seen(B, &poem(1), None, secs(2)));
+        assert_eq!(peers.other_mac(B, 1), Some(A));
+        assert_eq!(peers.other_mac(A, 1), Some(B));
+        assert_eq!(peers.other_mac(A, 2), None);
+    }
+
+    #[test]
+    fn expires_stale_peers() {
+        let mut peers = Peers::new(secs(30));
+        peers.seen(A, &poem(1), None, secs(0));
+        peers.seen(B, &poem(2), None, secs(20));
+        assert_eq!(peers.nearby(secs(25)), 2);
+        assert_eq!(peers.nearby(secs(40)), 1);
+
+        peers.expire(secs(40));
+        assert_eq!(peers.len(), 1);
+        peers.expire(secs(60));
+        assert!(peers.is_empty());
+    }
+}
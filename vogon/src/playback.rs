@@ -0,0 +1,298 @@
+//! The poems waiting to be shown, and the ones that were shown recently.
+//!
+//! Poems are received while the display is busy typing out another one. They
+//! wait in a short queue, so the display catches up with the most recent ones
+//! instead of falling further and further behind. Repeats and badges that
+//! broadcast a lot are filtered out before they get there.
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+use crate::selector::Strategy;
+use crate::transceiver::{Poem, PoemText};
+
+/// Number of poems that wait to be shown by default.
+pub const DEFAULT_MAX_QUEUE: usize = 4;
+/// Received poems are skipped when the same poem arrived this recently.
+pub const DEFAULT_REPEAT_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Poems from a badge are skipped when it sent one this recently.
+pub const DEFAULT_SOURCE_INTERVAL: Duration = Duration::from_secs(60);
+/// Number of shown poems that random poems avoid.
+pub const DEFAULT_HISTORY: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Picked at random because nothing was received.
+    Random,
+    /// Received over the radio or requested on the console.
+    Received,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Policy {
+    /// When more poems are waiting, the oldest one with the lowest priority
+    /// is dropped.
+    pub max_queue: usize,
+    /// Show received poems before random ones that were queued earlier.
+    pub received_first: bool,
+    /// Stop typing a random poem when a poem is received.
+    pub interrupt_random: bool,
+    pub repeat_window: Duration,
+    pub source_interval: Duration,
+    /// Number of shown poems that random poems avoid.
+    pub history: usize,
+    /// How random poems are picked.
+    pub selection: Strategy,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_queue: DEFAULT_MAX_QUEUE,
+            received_first: true,
+            interrupt_random: true,
+            repeat_window: DEFAULT_REPEAT_WINDOW,
+            source_interval: DEFAULT_SOURCE_INTERVAL,
+            history: DEFAULT_HISTORY,
+            selection: Strategy::default(),
+        }
+    }
+}
+
+/// Why a received poem isn't shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skip {
+    /// The same poem was received within the repeat window.
+    Repeat,
+    /// Its badge sent another poem within the source interval.
+    TooFrequent,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skip::Repeat => write!(f, "received it recently"),
+            Skip::TooFrequent => write!(f, "its badge sent one recently"),
+        }
+    }
+}
+
+/// The poems that were received and shown recently. Times are the duration
+/// since boot.
+pub struct History {
+    policy: Policy,
+    /// Poems that were let through, by the time they arrived.
+    received: VecDeque<(Duration, PoemText)>,
+    /// When a poem from a badge was last let through.
+    sources: BTreeMap<u8, Duration>,
+    /// The last poems shown, the most recent one last.
+    shown: VecDeque<PoemText>,
+}
+
+impl History {
+    pub fn new(policy: Policy) -> Self {
+        History {
+            policy,
+            received: VecDeque::new(),
+            sources: BTreeMap::new(),
+            shown: VecDeque::new(),
+        }
+    }
+
+    /// Check whether a received poem should be shown, and remember it if so.
+    pub fn admit(&mut self, poem: &Poem, now: Duration) -> Result<(), Skip> {
+        let window = self.policy.repeat_window;
+        self.received
+            .retain(|(at, _)| now.saturating_sub(*at) < window);
+        if self.received.iter().any(|(_, text)| *text == poem.text) {
+            return Err(Skip::Repeat);
+        }
+        if let Some(at) = self.sources.get(&poem.src) {
+            if now.saturating_sub(*at) < self.policy.source_interval {
+                return Err(Skip::TooFrequent);
+            }
+        }
+        self.received.push_back((now, poem.text.clone()));
+        self.sources.insert(poem.src, now);
+        Ok(())
+    }
+
+    /// Remember a poem that is shown.
+    pub fn shown(&mut self, text: &PoemText) {
+        self.shown.push_back(text.clone());
+        while self.shown.len() > self.policy.history {
+            self.shown.pop_front();
+        }
+    }
+
+    pub fn was_shown(&self, text: &PoemText) -> bool {
+        self.shown.contains(text)
+    }
+}
+
+pub struct Queue {
+    policy: Policy,
+    poems: VecDeque<(Priority, Poem)>,
+    /// Poems that were dropped because the queue was full.
+    pub dropped: u32,
+}
+
+impl Queue {
+    pub fn new(policy: Policy) -> Self {
+        Queue {
+            policy,
+            poems: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Queue a poem, returning the one that was dropped to make room: the
+    /// oldest one with the lowest priority, which can be `poem` itself.
+    pub fn push(&mut self, priority: Priority, poem: Poem) -> Option<Poem> {
+        self.poems.push_back((priority, poem));
+        if self.poems.len() <= self.policy.max_queue.max(1) {
+            return None;
+        }
+        self.dropped += 1;
+        let lowest = self.poems.iter().map(|(priority, _)| *priority).min()?;
+        let oldest = self
+            .poems
+            .iter()
+            .position(|(priority, _)| *priority == lowest)?;
+        self.poems.remove(oldest).map(|(_, poem)| poem)
+    }
+
+    /// The poem to show next.
+    pub fn pop(&mut self) -> Option<(Priority, Poem)> {
+        let next = if self.policy.received_first {
+            self.poems
+                .iter()
+                .position(|(priority, _)| *priority == Priority::Received)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        self.poems.remove(next)
+    }
+
+    /// Whether a poem with `priority` that is being shown should make way for
+    /// a queued one.
+    pub fn interrupts(&self, priority: Priority) -> bool {
+        self.policy.interrupt_random
+            && priority == Priority::Random
+            && self.poems.iter().any(|(p, _)| *p > priority)
+    }
+
+    pub fn len(&self) -> usize {
+        self.poems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.poems.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poem(id: u8) -> Poem {
+        Poem::local(id, 1)
+    }
+
+    fn from(src: u8, id: u8) -> Poem {
+        Poem::local(id, src)
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn pop_id(queue: &mut Queue) -> Option<(Priority, u8)> {
+        queue.pop().map(|(priority, poem)| match poem.text {
+            PoemText::Library(id) => (priority, id),
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn drops_the_oldest() {
+        let mut queue = Queue::new(Policy {
+            max_queue: 2,
+            ..Default::default()
+        });
+        assert!(queue.push(Priority::Received, poem(1)).is_none());
+        assert!(queue.push(Priority::Received, poem(2)).is_none());
+        assert!(queue.push(Priority::Received, poem(3)).is_some());
+        assert_eq!((queue.len(), queue.dropped), (2, 1));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Received, 2)));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Received, 3)));
+        assert_eq!(pop_id(&mut queue), None);
+
+        // Random poems go first, and don't push out received ones
+        queue.push(Priority::Received, poem(4));
+        queue.push(Priority::Random, poem(5));
+        let dropped = queue.push(Priority::Received, poem(6)).unwrap();
+        assert_eq!(dropped.text, PoemText::Library(5));
+        let dropped = queue.push(Priority::Random, poem(7)).unwrap();
+        assert_eq!(dropped.text, PoemText::Library(7));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Received, 4)));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Received, 6)));
+    }
+
+    #[test]
+    fn received_first() {
+        let mut queue = Queue::new(Policy::default());
+        queue.push(Priority::Random, poem(1));
+        assert!(!queue.interrupts(Priority::Random));
+        queue.push(Priority::Received, poem(2));
+        assert!(queue.interrupts(Priority::Random));
+        assert!(!queue.interrupts(Priority::Received));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Received, 2)));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Random, 1)));
+
+        let mut queue = Queue::new(Policy {
+            received_first: false,
+            interrupt_random: false,
+            ..Default::default()
+        });
+        queue.push(Priority::Random, poem(1));
+        queue.push(Priority::Received, poem(2));
+        assert!(!queue.interrupts(Priority::Random));
+        assert_eq!(pop_id(&mut queue), Some((Priority::Random, 1)));
+    }
+
+    #[test]
+    fn skips_repeats() {
+        let mut history = History::new(Policy {
+            repeat_window: 60 * SECOND,
+            source_interval: 10 * SECOND,
+            ..Default::default()
+        });
+        assert_eq!(history.admit(&from(1, 1), SECOND), Ok(()));
+        assert_eq!(history.admit(&from(2, 1), 2 * SECOND), Err(Skip::Repeat));
+        assert_eq!(
+            history.admit(&from(1, 2), 5 * SECOND),
+            Err(Skip::TooFrequent)
+        );
+        assert_eq!(history.admit(&from(2, 2), 5 * SECOND), Ok(()));
+        assert_eq!(history.admit(&from(1, 3), 11 * SECOND), Ok(()));
+        // The repeat window has passed
+        assert_eq!(history.admit(&from(3, 1), 61 * SECOND), Ok(()));
+    }
+
+    #[test]
+    fn forgets_old_poems() {
+        let mut history = History::new(Policy {
+            history: 3,
+            ..Default::default()
+        });
+        for id in 0..4 {
+            history.shown(&PoemText::Library(id));
+        }
+        assert!(!history.was_shown(&PoemText::Library(0)));
+        assert!((1..4).all(|id| history.was_shown(&PoemText::Library(id))));
+    }
+}
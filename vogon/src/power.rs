@@ -0,0 +1,246 @@
+//! Power profiles, and how much current they draw.
+//!
+//! A badge runs for hours on a small LiPo. During the parade it has to hear
+//! every poem, in between it sits in a box and should use as little as it
+//! can.
+//!
+//! The chip can only light sleep while the radio does. In the parade the
+//! radio sleeps for 40 ms at a time, about as long as the display waits
+//! between frames, and every frame is sent twice so a badge that slept
+//! through one hears the other.
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::transceiver::SEND_DELAY_RANGE;
+
+/// Current the ESP32 draws with the CPU running, in mA, by clock in MHz. From
+/// the datasheet, without the radio.
+const CPU_ACTIVE: [(u32, f32); 3] = [(80, 20.0), (160, 27.0), (240, 40.0)];
+/// Current in light sleep.
+const CPU_LIGHT_SLEEP: f32 = 0.8;
+/// Share of the time the CPU is busy, the rest it waits for the next frame
+/// or packet. A guess, typing poems keeps it the busiest.
+const CPU_BUSY: f32 = 0.3;
+/// Current with the radio listening.
+const RADIO_RX: f32 = 95.0;
+/// Current of the modem between wake windows.
+const RADIO_SLEEP: f32 = 1.0;
+/// Current of a 128x32 OLED at full contrast with a screen of text, which
+/// lights about a fifth of the pixels.
+const DISPLAY_FULL: f32 = 10.0;
+/// Current of the OLED controller with the panel off.
+const DISPLAY_OFF: f32 = 0.01;
+
+/// Frames are sent again after this long. It is longer than the radio
+/// sleeps in the parade profile, and shorter than it listens, so one of the
+/// two arrives while it listens.
+pub const RESEND_AFTER: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// Hear every poem and show it right away.
+    #[default]
+    Parade,
+    /// Between parades: the display is off, the radio listens now and then
+    /// and poems are broadcast rarely.
+    Storage,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidProfile(pub String);
+
+impl fmt::Display for InvalidProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid power profile {:?}, expected parade or storage",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidProfile {}
+
+impl FromStr for Profile {
+    type Err = InvalidProfile;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "parade" => Ok(Profile::Parade),
+            "storage" => Ok(Profile::Storage),
+            _ => Err(InvalidProfile(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Parade => write!(f, "parade"),
+            Profile::Storage => write!(f, "storage"),
+        }
+    }
+}
+
+/// The radio listens for `window` every `interval`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DutyCycle {
+    pub window: Duration,
+    pub interval: Duration,
+}
+
+impl DutyCycle {
+    /// How long the radio sleeps between wake windows.
+    pub fn sleep(&self) -> Duration {
+        self.interval.saturating_sub(self.window)
+    }
+
+    /// Share of the time the radio listens.
+    pub fn ratio(&self) -> f32 {
+        (self.window.as_secs_f32() / self.interval.as_secs_f32()).min(1.0)
+    }
+}
+
+/// How a badge saves power.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PowerMode {
+    /// CPU clock when busy, 80, 160 or 240 MHz.
+    pub max_cpu_mhz: u32,
+    /// CPU clock when idle.
+    pub min_cpu_mhz: u32,
+    /// Sleep while all threads wait, like between frames of the display.
+    /// The chip can't sleep while the radio listens, so this only saves
+    /// power with a duty cycled radio.
+    pub light_sleep: bool,
+    /// `None` to keep the radio listening all the time.
+    pub radio: Option<DutyCycle>,
+    /// Seconds between broadcasts.
+    pub send_delay: Range<u64>,
+    pub display: bool,
+}
+
+impl Profile {
+    pub fn mode(self) -> PowerMode {
+        match self {
+            Profile::Parade => PowerMode {
+                max_cpu_mhz: 160,
+                min_cpu_mhz: 80,
+                light_sleep: true,
+                radio: Some(DutyCycle {
+                    window: Duration::from_millis(60),
+                    interval: Duration::from_millis(100),
+                }),
+                send_delay: SEND_DELAY_RANGE,
+                display: true,
+            },
+            Profile::Storage => PowerMode {
+                max_cpu_mhz: 80,
+                min_cpu_mhz: 40,
+                light_sleep: true,
+                radio: Some(DutyCycle {
+                    window: Duration::from_millis(50),
+                    interval: Duration::from_millis(1000),
+                }),
+                send_delay: 300..600,
+                display: false,
+            },
+        }
+    }
+}
+
+/// An estimate of the current a badge draws, in mA.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Budget {
+    pub cpu: f32,
+    pub radio: f32,
+    pub display: f32,
+}
+
+impl Budget {
+    pub fn total(&self) -> f32 {
+        self.cpu + self.radio + self.display
+    }
+
+    /// Hours a battery of `capacity` mAh lasts.
+    pub fn hours(&self, capacity: u32) -> f32 {
+        capacity as f32 / self.total()
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "~{:.0} mA (cpu {:.1}, radio {:.1}, display {:.1})",
+            self.total(),
+            self.cpu,
+            self.radio,
+            self.display
+        )
+    }
+}
+
+impl PowerMode {
+    /// Estimate the current draw with the display at `contrast`. These are
+    /// rough figures from the datasheets, to compare modes, not a
+    /// measurement.
+    pub fn budget(&self, contrast: u8) -> Budget {
+        let active = |mhz: u32| {
+            CPU_ACTIVE
+                .iter()
+                .find(|(clock, _)| *clock >= mhz)
+                .map_or(CPU_ACTIVE[2].1, |(_, current)| *current)
+        };
+        let idle = if self.light_sleep && self.radio.is_some() {
+            CPU_LIGHT_SLEEP
+        } else {
+            active(self.min_cpu_mhz)
+        };
+        let cpu = CPU_BUSY * active(self.max_cpu_mhz) + (1.0 - CPU_BUSY) * idle;
+        let radio = match self.radio {
+            None => RADIO_RX,
+            Some(duty) => duty.ratio() * RADIO_RX + (1.0 - duty.ratio()) * RADIO_SLEEP,
+        };
+        let display = if self.display {
+            DISPLAY_OFF + DISPLAY_FULL * contrast as f32 / 255.0
+        } else {
+            DISPLAY_OFF
+        };
+        Budget {
+            cpu,
+            radio,
+            display,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_saves_power() {
+        assert_eq!("storage".parse(), Ok(Profile::Storage));
+        assert!("turbo".parse::<Profile>().is_err());
+
+        let parade = Profile::Parade.mode().budget(0x7f);
+        let storage = Profile::Storage.mode().budget(0x7f);
+        // The radio takes most of it
+        assert!(parade.radio > parade.cpu + parade.display, "{}", parade);
+        assert!(storage.total() < 15.0, "{}", storage);
+        assert!(storage.hours(1000) > 5.0 * parade.hours(1000));
+    }
+
+    #[test]
+    fn parade_hears_every_frame() {
+        let duty = Profile::Parade.mode().radio.unwrap();
+        assert!(duty.sleep() < RESEND_AFTER && RESEND_AFTER <= duty.window);
+        // Frames sent at any time in the interval, and again later
+        let interval = duty.interval.as_millis() as u64;
+        let listening = |ms: u64| ms % interval < duty.window.as_millis() as u64;
+        let resend = RESEND_AFTER.as_millis() as u64;
+        assert!((0..interval).all(|ms| listening(ms) || listening(ms + resend)));
+    }
+}
@@ -0,0 +1,198 @@
+//! Keeps the OLED from burning in.
+//!
+//! During a parade a badge shows the same status screen at the same pixels
+//! for hours. Static screens are moved around by a few pixels every now and
+//! then, and the panel is dimmed and eventually turned off when no poems
+//! arrive for a while.
+use std::time::Duration;
+
+use embedded_graphics::prelude::Point;
+
+/// Pixels static screens move away from the center at most.
+pub const DEFAULT_SHIFT: u32 = 2;
+/// How long static screens stay at the same pixels.
+pub const DEFAULT_SHIFT_INTERVAL: Duration = Duration::from_secs(60);
+/// Contrast of the panel, which is how bright an OLED is.
+pub const DEFAULT_CONTRAST: u8 = 0x7f;
+pub const DEFAULT_DIMMED_CONTRAST: u8 = 0x08;
+pub const DEFAULT_DIM_AFTER: Duration = Duration::from_secs(5 * 60);
+pub const DEFAULT_OFF_AFTER: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Protection {
+    /// Pixels static screens move away from the center at most, 0 to keep
+    /// them in place.
+    pub shift: u32,
+    pub shift_interval: Duration,
+    pub contrast: u8,
+    pub dimmed_contrast: u8,
+    /// Dim the panel when no poem was received this long, `None` to never
+    /// dim it.
+    pub dim_after: Option<Duration>,
+    /// Turn the panel off when no poem was received this long, `None` to
+    /// keep it on.
+    pub off_after: Option<Duration>,
+}
+
+impl Default for Protection {
+    fn default() -> Self {
+        Protection {
+            shift: DEFAULT_SHIFT,
+            shift_interval: DEFAULT_SHIFT_INTERVAL,
+            contrast: DEFAULT_CONTRAST,
+            dimmed_contrast: DEFAULT_DIMMED_CONTRAST,
+            dim_after: Some(DEFAULT_DIM_AFTER),
+            off_after: Some(DEFAULT_OFF_AFTER),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    On,
+    Dimmed,
+    /// The screensaver: the panel is off until a poem arrives.
+    Off,
+}
+
+/// Decides how the panel is lit. Times are the duration since boot.
+pub struct ScreenSaver {
+    protection: Protection,
+    last_activity: Duration,
+    /// The mode that was last returned by `update`.
+    mode: Option<Mode>,
+    /// Keep the panel dimmed to save the battery.
+    battery_low: bool,
+}
+
+impl ScreenSaver {
+    pub fn new(protection: Protection, now: Duration) -> Self {
+        ScreenSaver {
+            protection,
+            last_activity: now,
+            mode: None,
+            battery_low: false,
+        }
+    }
+
+    pub fn protection(&self) -> &Protection {
+        &self.protection
+    }
+
+    /// Remember that a poem arrived, which wakes the panel up.
+    pub fn activity(&mut self, now: Duration) {
+        self.last_activity = now;
+    }
+
+    /// Dim the panel while the battery is low, even when poems arrive.
+    pub fn set_battery_low(&mut self, low: bool) {
+        self.battery_low = low;
+    }
+
+    pub fn mode(&self, now: Duration) -> Mode {
+        let idle = now.saturating_sub(self.last_activity);
+        let passed = |after: Option<Duration>| after.is_some_and(|after| idle >= after);
+        if passed(self.protection.off_after) {
+            Mode::Off
+        } else if self.battery_low || passed(self.protection.dim_after) {
+            Mode::Dimmed
+        } else {
+            Mode::On
+        }
+    }
+
+    /// The mode, if it changed since the last call and should be applied to
+    /// the panel.
+    pub fn update(&mut self, now: Duration) -> Option<Mode> {
+        let mode = self.mode(now);
+        if self.mode == Some(mode) {
+            return None;
+        }
+        self.mode = Some(mode);
+        Some(mode)
+    }
+
+    /// The contrast to use in `mode`.
+    pub fn contrast(&self, mode: Mode) -> u8 {
+        match mode {
+            Mode::On | Mode::Off => self.protection.contrast,
+            Mode::Dimmed => self.protection.dimmed_contrast,
+        }
+    }
+
+    /// How far static screens are moved from where they belong. The offset
+    /// walks every position in a square around the center, a row at a time
+    /// and back, so it never jumps far.
+    pub fn shift(&self, now: Duration) -> Point {
+        let shift = self.protection.shift as i32;
+        let side = 2 * shift + 1;
+        let interval = self.protection.shift_interval.as_millis().max(1);
+        let i = ((now.as_millis() / interval) % (side * side) as u128) as i32;
+        let (row, column) = (i / side, i % side);
+        let column = if row % 2 == 0 {
+            column
+        } else {
+            side - 1 - column
+        };
+        Point::new(column - shift, row - shift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn dims_and_turns_off() {
+        let mut saver = ScreenSaver::new(Protection::default(), Duration::ZERO);
+        assert_eq!(saver.update(Duration::ZERO), Some(Mode::On));
+        assert_eq!(saver.update(MINUTE), None);
+        assert_eq!(saver.update(5 * MINUTE), Some(Mode::Dimmed));
+        assert_eq!(saver.contrast(Mode::Dimmed), DEFAULT_DIMMED_CONTRAST);
+        assert_eq!(saver.update(30 * MINUTE), Some(Mode::Off));
+        saver.activity(31 * MINUTE);
+        assert_eq!(saver.update(31 * MINUTE), Some(Mode::On));
+        saver.set_battery_low(true);
+        assert_eq!(saver.update(32 * MINUTE), Some(Mode::Dimmed));
+        assert_eq!(saver.update(61 * MINUTE), Some(Mode::Off));
+
+        let saver = ScreenSaver::new(
+            Protection {
+                dim_after: None,
+                off_after: None,
+                ..Default::default()
+            },
+            Duration::ZERO,
+        );
+        assert_eq!(saver.mode(24 * 60 * MINUTE), Mode::On);
+    }
+
+    #[test]
+    fn shifts_a_pixel_at_a_time() {
+        let saver = ScreenSaver::new(Protection::default(), Duration::ZERO);
+        let offsets: Vec<Point> = (0..26).map(|i| saver.shift(i * MINUTE)).collect();
+        assert_eq!(offsets[0], Point::new(-2, -2));
+        assert_eq!(offsets[5], Point::new(2, -1));
+        // Every position once, then around again
+        let mut distinct = offsets[..25].to_vec();
+        distinct.sort_by_key(|p| (p.x, p.y));
+        distinct.dedup();
+        assert_eq!(distinct.len(), 25);
+        assert_eq!(offsets[25], offsets[0]);
+        for pair in offsets[..25].windows(2) {
+            let step = pair[1] - pair[0];
+            assert_eq!(step.x.abs() + step.y.abs(), 1);
+        }
+
+        let saver = ScreenSaver::new(
+            Protection {
+                shift: 0,
+                ..Default::default()
+            },
+            Duration::ZERO,
+        );
+        assert_eq!(saver.shift(7 * MINUTE), Point::zero());
+    }
+}
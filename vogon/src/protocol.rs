@@ -0,0 +1,457 @@
+//! Wire format of the packets that badges broadcast over ESP-NOW.
+//!
+//! Every frame has the following layout (multi-byte values are little endian):
+//!
+//! ```text
+//! +-------+---------+------+-----+-----+---------+-------+
+//! | magic | version | type | src | seq | payload | crc16 |
+//! |  2    |    1    |  1   |  1  |  2  |    n    |   2   |
+//! +-------+---------+------+-----+-----+---------+-------+
+//! ```
+//!
+//! The CRC is a CRC-16/CCITT-FALSE over everything that precedes it. `src`
+//! and `seq` are those of the badge that sent the frame, relayed poems carry
+//! the device id and sequence number of their origin in the payload.
+use std::fmt;
+
+use crate::layout::MAX_POEM_LINES;
+
+/// Marks a frame as one of ours, so frames from other ESP-NOW devices on the
+/// same channel can be told apart from corrupted ones.
+pub const MAGIC: [u8; 2] = *b"VP";
+pub const VERSION: u8 = 1;
+
+/// ESP-NOW frames carry at most 250 bytes.
+pub const MAX_FRAME_LEN: usize = 250;
+
+const HEADER_LEN: usize = 7;
+const CRC_LEN: usize = 2;
+/// Hash, index and count of a fragment.
+const FRAGMENT_HEADER_LEN: usize = 6;
+/// Most poem text that fits in a single fragment.
+pub const MAX_FRAGMENT_LEN: usize = MAX_FRAME_LEN - HEADER_LEN - CRC_LEN - FRAGMENT_HEADER_LEN;
+/// Longest poem text that is sent, in bytes: `MAX_POEM_LINES` lines of 32
+/// characters, which can take 2 bytes in UTF-8.
+pub const MAX_TEXT_LEN: usize = MAX_POEM_LINES * 32 * 2;
+/// Fragments of the longest poem.
+pub const MAX_FRAGMENTS: usize = MAX_TEXT_LEN.div_ceil(MAX_FRAGMENT_LEN);
+/// Battery level of a badge that doesn't measure it.
+const BATTERY_UNKNOWN: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    Poem = 1,
+    Relay = 2,
+    Fragment = 3,
+    Status = 4,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = ParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MessageType::Poem),
+            2 => Ok(MessageType::Relay),
+            3 => Ok(MessageType::Fragment),
+            4 => Ok(MessageType::Status),
+            t => Err(ParseError::UnknownType(t)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// Id of a poem in the library, see `corpus`.
+    Poem { id: u8 },
+    /// A poem rebroadcast by a badge that isn't the one it came from.
+    Relay {
+        /// Device id of the badge that first broadcast the poem.
+        origin: u8,
+        /// Sequence number of the original packet.
+        origin_seq: u16,
+        /// How many more times the poem may be relayed.
+        ttl: u8,
+        /// How many times the poem was relayed, including this one.
+        hops: u8,
+        id: u8,
+    },
+    /// Part of the text of a poem, for poems the receiver might not have.
+    Fragment {
+        /// `content_hash` of the whole text.
+        hash: u32,
+        index: u8,
+        count: u8,
+        data: Vec<u8>,
+    },
+    /// Sent now and then, so other badges know how the sender is doing.
+    Status {
+        /// Charge of the battery in percent, `None` if it isn't measured.
+        battery: Option<u8>,
+    },
+}
+
+impl Message {
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            Message::Poem { .. } => MessageType::Poem,
+            Message::Relay { .. } => MessageType::Relay,
+            Message::Fragment { .. } => MessageType::Fragment,
+            Message::Status { .. } => MessageType::Status,
+        }
+    }
+
+    fn write_payload(&self, buf: &mut Vec<u8>) {
+        match self {
+            Message::Poem { id } => buf.push(*id),
+            Message::Relay {
+                origin,
+                origin_seq,
+                ttl,
+                hops,
+                id,
+            } => {
+                buf.push(*origin);
+                buf.extend_from_slice(&origin_seq.to_le_bytes());
+                buf.extend_from_slice(&[*ttl, *hops, *id]);
+            }
+            Message::Fragment {
+                hash,
+                index,
+                count,
+                data,
+            } => {
+                buf.extend_from_slice(&hash.to_le_bytes());
+                buf.extend_from_slice(&[*index, *count]);
+                buf.extend_from_slice(data);
+            }
+            Message::Status { battery } => buf.push(battery.unwrap_or(BATTERY_UNKNOWN)),
+        }
+    }
+
+    fn parse_payload(t: MessageType, payload: &[u8]) -> Result<Self, ParseError> {
+        let message = match (t, payload) {
+            (MessageType::Poem, [id]) => Message::Poem { id: *id },
+            (MessageType::Relay, [origin, seq_lo, seq_hi, ttl, hops, id]) => Message::Relay {
+                origin: *origin,
+                origin_seq: u16::from_le_bytes([*seq_lo, *seq_hi]),
+                ttl: *ttl,
+                hops: *hops,
+                id: *id,
+            },
+            (MessageType::Fragment, [h0, h1, h2, h3, index, count, data @ ..])
+                if data.len() <= MAX_FRAGMENT_LEN =>
+            {
+                Message::Fragment {
+                    hash: u32::from_le_bytes([*h0, *h1, *h2, *h3]),
+                    index: *index,
+                    count: *count,
+                    data: data.to_vec(),
+                }
+            }
+            (MessageType::Status, [battery]) => Message::Status {
+                battery: Some(*battery).filter(|b| *b != BATTERY_UNKNOWN),
+            },
+            _ => {
+                return Err(ParseError::BadLength {
+                    message_type: t,
+                    len: payload.len(),
+                })
+            }
+        };
+        Ok(message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    /// Device id of the sender.
+    pub src: u8,
+    /// Per-sender sequence number, wraps around.
+    pub seq: u16,
+    pub message: Message,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The frame is shorter than an empty packet.
+    TooShort(usize),
+    /// The frame doesn't start with `MAGIC`, it's probably not ours.
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownType(u8),
+    /// The payload length doesn't match what the message type expects.
+    BadLength {
+        message_type: MessageType,
+        len: usize,
+    },
+    BadChecksum {
+        expected: u16,
+        actual: u16,
+    },
+}
+
+impl ParseError {
+    /// Whether the frame was sent by something that doesn't speak our protocol
+    /// at all, as opposed to a badge sending a damaged or newer frame.
+    pub fn is_foreign(&self) -> bool {
+        matches!(self, ParseError::TooShort(_) | ParseError::BadMagic)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort(len) => write!(f, "frame too short ({} bytes)", len),
+            ParseError::BadMagic => write!(f, "bad magic"),
+            ParseError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            ParseError::UnknownType(t) => write!(f, "unknown message type {}", t),
+            ParseError::BadLength { message_type, len } => {
+                write!(f, "bad payload length {} for {:?}", len, message_type)
+            }
+            ParseError::BadChecksum { expected, actual } => write!(
+                f,
+                "bad checksum (expected {:04x}, got {:04x})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Packet {
+    pub fn new(src: u8, seq: u16, message: Message) -> Self {
+        Packet { src, seq, message }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MAX_FRAME_LEN);
+        buf.extend_from_slice(&MAGIC);
+        buf.push(VERSION);
+        buf.push(self.message.message_type() as u8);
+        buf.push(self.src);
+        buf.extend_from_slice(&self.seq.to_le_bytes());
+        self.message.write_payload(&mut buf);
+        let crc = crc16(&buf);
+        buf.extend_from_slice(&crc.to_le_bytes());
+        debug_assert!(buf.len() <= MAX_FRAME_LEN);
+        buf
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        if data.len() < HEADER_LEN + CRC_LEN {
+            return Err(ParseError::TooShort(data.len()));
+        }
+        if data[..MAGIC.len()] != MAGIC {
+            return Err(ParseError::BadMagic);
+        }
+
+        let (body, crc) = data.split_at(data.len() - CRC_LEN);
+        let expected = u16::from_le_bytes([crc[0], crc[1]]);
+        let actual = crc16(body);
+        if expected != actual {
+            return Err(ParseError::BadChecksum { expected, actual });
+        }
+
+        let version = body[2];
+        if version != VERSION {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+        let message_type = MessageType::try_from(body[3])?;
+        let src = body[4];
+        let seq = u16::from_le_bytes([body[5], body[6]]);
+        let message = Message::parse_payload(message_type, &body[HEADER_LEN..])?;
+
+        Ok(Packet { src, seq, message })
+    }
+}
+
+/// Split the text of a poem into fragments, or `None` if it is longer than
+/// `MAX_TEXT_LEN`.
+pub fn fragment(text: &str) -> Option<Vec<Message>> {
+    if text.len() > MAX_TEXT_LEN {
+        return None;
+    }
+    let hash = content_hash(text.as_bytes());
+    let chunks: Vec<&[u8]> = text.as_bytes().chunks(MAX_FRAGMENT_LEN).collect();
+    let count = u8::try_from(chunks.len().max(1)).ok()?;
+    Some(
+        (0..count)
+            .map(|index| Message::Fragment {
+                hash,
+                index,
+                count,
+                data: chunks.get(index as usize).unwrap_or(&&[][..]).to_vec(),
+            })
+            .collect(),
+    )
+}
+
+/// FNV-1a, used to check that a reassembled poem is complete and to tell
+/// poems apart.
+pub fn content_hash(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poem_packet() -> Packet {
+        Packet::new(7, 0x1234, Message::Poem { id: 41 })
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let packet = poem_packet();
+        let frame = packet.serialize();
+        assert_eq!(frame.len(), HEADER_LEN + 1 + CRC_LEN);
+        assert_eq!(Packet::parse(&frame), Ok(packet));
+    }
+
+    #[test]
+    fn relay_roundtrip() {
+        let packet = Packet::new(
+            3,
+            1,
+            Message::Relay {
+                origin: 7,
+                origin_seq: 0x1234,
+                ttl: 2,
+                hops: 1,
+                id: 41,
+            },
+        );
+        let frame = packet.serialize();
+        assert_eq!(frame.len(), HEADER_LEN + 6 + CRC_LEN);
+        assert_eq!(Packet::parse(&frame), Ok(packet));
+    }
+
+    #[test]
+    fn status_roundtrip() {
+        for battery in [Some(0), Some(87), None] {
+            let packet = Packet::new(3, 1, Message::Status { battery });
+            let frame = packet.serialize();
+            assert_eq!(frame.len(), HEADER_LEN + 1 + CRC_LEN);
+            assert_eq!(Packet::parse(&frame), Ok(packet));
+        }
+    }
+
+    #[test]
+    fn fragments() {
+        let text = "Oh freddled gruntbuggly ".repeat(20);
+        let fragments = fragment(&text).unwrap();
+        assert_eq!(fragments.len(), 3);
+
+        let mut data = vec![];
+        for (i, message) in fragments.into_iter().enumerate() {
+            let packet = Packet::new(7, i as u16, message);
+            let frame = packet.serialize();
+            assert!(frame.len() <= MAX_FRAME_LEN);
+            assert_eq!(Packet::parse(&frame), Ok(packet.clone()));
+            if let Message::Fragment {
+                hash,
+                index,
+                count,
+                data: d,
+            } = packet.message
+            {
+                assert_eq!(
+                    (hash, index, count),
+                    (content_hash(text.as_bytes()), i as u8, 3)
+                );
+                data.extend(d);
+            }
+        }
+        assert_eq!(data, text.as_bytes());
+
+        assert_eq!(fragment("").unwrap().len(), 1);
+        assert_eq!(
+            fragment(&"x".repeat(MAX_TEXT_LEN)).unwrap().len(),
+            MAX_FRAGMENTS
+        );
+        assert_eq!(fragment(&"x".repeat(MAX_TEXT_LEN + 1)), None);
+    }
+
+    #[test]
+    fn short_frames() {
+        assert_eq!(Packet::parse(&[]), Err(ParseError::TooShort(0)));
+        assert_eq!(Packet::parse(&[3]), Err(ParseError::TooShort(1)));
+        assert_eq!(Packet::parse(&[3, 7]), Err(ParseError::TooShort(2)));
+        assert_eq!(Packet::parse(b"VP\x01"), Err(ParseError::TooShort(3)));
+    }
+
+    #[test]
+    fn foreign_frame() {
+        let err = Packet::parse(b"hello from another device").unwrap_err();
+        assert_eq!(err, ParseError::BadMagic);
+        assert!(err.is_foreign());
+    }
+
+    #[test]
+    fn corrupted_frame() {
+        let mut frame = poem_packet().serialize();
+        frame[7] ^= 0x01;
+        let err = Packet::parse(&frame).unwrap_err();
+        assert!(matches!(err, ParseError::BadChecksum { .. }));
+        assert!(!err.is_foreign());
+    }
+
+    fn with_crc(mut body: Vec<u8>) -> Vec<u8> {
+        let crc = crc16(&body);
+        body.extend_from_slice(&crc.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let frame = with_crc(vec![b'V', b'P', 2, 1, 7, 0, 0, 1]);
+        assert_eq!(
+            Packet::parse(&frame),
+            Err(ParseError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn unknown_type() {
+        let frame = with_crc(vec![b'V', b'P', VERSION, 0xee, 7, 0, 0, 1]);
+        assert_eq!(Packet::parse(&frame), Err(ParseError::UnknownType(0xee)));
+    }
+
+    #[test]
+    fn bad_payload_length() {
+        let frame = with_crc(vec![b'V', b'P', VERSION, 1, 7, 0, 0, 1, 2]);
+        assert_eq!(
+            Packet::parse(&frame),
+            Err(ParseError::BadLength {
+                message_type: MessageType::Poem,
+                len: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Relaying poems, so they ripple down the parade beyond the range of a
+//! single badge.
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Hops a poem makes when relaying is enabled.
+pub const DEFAULT_TTL: u8 = 3;
+
+/// Random delay before relaying a poem in milliseconds, so badges that
+/// received the same frame don't all rebroadcast at once.
+pub const RELAY_DELAY_RANGE: Range<u64> = 50..500;
+
+/// Number of poems remembered for duplicate suppression.
+const SEEN_CAPACITY: usize = 64;
+
+/// Recently received poems, by origin device and sequence number.
+pub struct Seen {
+    recent: VecDeque<(u8, u16)>,
+}
+
+impl Default for Seen {
+    fn default() -> Self {
+        Seen {
+            recent: VecDeque::with_capacity(SEEN_CAPACITY),
+        }
+    }
+}
+
+impl Seen {
+    /// Remember a poem, returning whether it wasn't seen before.
+    pub fn insert(&mut self, origin: u8, seq: u16) -> bool {
+        if self.recent.contains(&(origin, seq)) {
+            return false;
+        }
+        if self.recent.len() == SEEN_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((origin, seq));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppresses_duplicates() {
+        let mut seen = Seen::default();
+        assert!(seen.insert(1, 0));
+        assert!(seen.insert(2, 0));
+        assert!(seen.insert(1, 1));
+        assert!(!seen.insert(1, 0));
+        assert!(!seen.insert(2, 0));
+    }
+
+    #[test]
+    fn forgets_old_poems() {
+        let mut seen = Seen::default();
+        for seq in 0..=SEEN_CAPACITY as u16 {
+            assert!(seen.insert(1, seq));
+        }
+        assert!(seen.insert(1, 0));
+        assert!(!seen.insert(1, SEEN_CAPACITY as u16));
+    }
+}
@@ -0,0 +1,474 @@
+//! The displays everything is drawn on.
+//!
+//! A `Screen` is a monochrome display that is drawn in a buffer and flushed.
+//! `Panel` is the OLED a badge is built with, which sets the size of the
+//! screen. A panel mounted upside down or on its side is drawn through
+//! `Rotated`, which turns and mirrors what is drawn by the `Rotation` and
+//! mirroring in its `Orientation`, so it can be laid out for the turned size.
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+/// A monochrome display that buffers drawing operations until they are
+/// flushed.
+///
+/// The firmware implements this for the SSD1306, the simulator for an
+/// in-memory `Framebuffer`.
+pub trait Screen: DrawTarget<Color = BinaryColor, Error: std::fmt::Debug> {
+    /// Write the buffer to the panel.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Wait between frames of an effect.
+    fn delay(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    /// Set the contrast of the panel, which is how bright an OLED is.
+    fn set_contrast(&mut self, _contrast: u8) -> Result<()> {
+        Ok(())
+    }
+
+    /// Turn the panel on or off. What is drawn while it's off shows when it
+    /// is turned on again.
+    fn set_on(&mut self, _on: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Controller {
+    #[default]
+    Ssd1306,
+    Sh1106,
+}
+
+/// How the panel is connected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interface {
+    #[default]
+    I2c,
+    Spi,
+}
+
+/// An OLED panel the firmware has a driver for. The default is the 128x32
+/// SSD1306 on I2C the badges were built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Panel {
+    pub controller: Controller,
+    pub size: Size,
+    pub interface: Interface,
+}
+
+impl Default for Panel {
+    fn default() -> Self {
+        Panel {
+            controller: Controller::Ssd1306,
+            size: Size::new(128, 32),
+            interface: Interface::I2c,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidPanel(pub String);
+
+impl fmt::Display for InvalidPanel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid panel {:?}, expected ssd1306-128x32, ssd1306-128x64 or sh1106-128x64, \
+             optionally followed by -spi",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidPanel {}
+
+/// Parses `<controller>-<width>x<height>`, followed by `-spi` for panels on
+/// SPI instead of I2C, like `sh1106-128x64-spi`.
+impl FromStr for Panel {
+    type Err = InvalidPanel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidPanel(s.to_string());
+        let (rest, interface) = match s.trim().strip_suffix("-spi") {
+            Some(rest) => (rest, Interface::Spi),
+            None => (s.trim(), Interface::I2c),
+        };
+        let (controller, size) = match rest {
+            "ssd1306-128x32" => (Controller::Ssd1306, Size::new(128, 32)),
+            "ssd1306-128x64" => (Controller::Ssd1306, Size::new(128, 64)),
+            "sh1106-128x64" => (Controller::Sh1106, Size::new(128, 64)),
+            _ => return Err(invalid()),
+        };
+        Ok(Panel {
+            controller,
+            size,
+            interface,
+        })
+    }
+}
+
+/// How far what is drawn is turned clockwise on the panel, to make up for
+/// how the panel is mounted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    pub fn degrees(self) -> u16 {
+        match self {
+            Rotation::Rotate0 => 0,
+            Rotation::Rotate90 => 90,
+            Rotation::Rotate180 => 180,
+            Rotation::Rotate270 => 270,
+        }
+    }
+
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::Rotate0),
+            90 => Some(Rotation::Rotate90),
+            180 => Some(Rotation::Rotate180),
+            270 => Some(Rotation::Rotate270),
+            _ => None,
+        }
+    }
+
+    /// The size of a panel of `size` when it's turned.
+    pub fn size(self, size: Size) -> Size {
+        match self {
+            Rotation::Rotate0 | Rotation::Rotate180 => size,
+            Rotation::Rotate90 | Rotation::Rotate270 => Size::new(size.height, size.width),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRotation(pub String);
+
+impl fmt::Display for InvalidRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid rotation {:?}, expected 0, 90, 180 or 270",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidRotation {}
+
+impl FromStr for Rotation {
+    type Err = InvalidRotation;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .ok()
+            .and_then(Rotation::from_degrees)
+            .ok_or_else(|| InvalidRotation(s.to_string()))
+    }
+}
+
+/// How the panel is mounted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    /// Flipped left to right, for panels that are read through a mirror.
+    pub mirrored: bool,
+}
+
+/// A screen that is mounted turned or mirrored. Whatever is drawn on it is
+/// laid out for its size as it is mounted, 32x128 for a 128x32 panel that is
+/// turned 90 degrees.
+pub struct Rotated<S> {
+    screen: S,
+    /// Changes to the orientation, which are applied on the next flush.
+    orientation: Arc<Mutex<Orientation>>,
+    current: Orientation,
+}
+
+impl<S: Screen> Rotated<S> {
+    pub fn new(screen: S, orientation: Arc<Mutex<Orientation>>) -> Self {
+        let current = *orientation.lock().unwrap();
+        Rotated {
+            screen,
+            orientation,
+            current,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.screen
+    }
+}
+
+impl Orientation {
+    /// Where a point on the mounted screen is on a panel of `panel`.
+    fn to_panel(self, panel: Size, point: Point) -> Point {
+        let size = self.rotation.size(panel);
+        let x = if self.mirrored {
+            size.width as i32 - 1 - point.x
+        } else {
+            point.x
+        };
+        let y = point.y;
+        let (width, height) = (panel.width as i32, panel.height as i32);
+        match self.rotation {
+            Rotation::Rotate0 => Point::new(x, y),
+            Rotation::Rotate90 => Point::new(width - 1 - y, x),
+            Rotation::Rotate180 => Point::new(width - 1 - x, height - 1 - y),
+            Rotation::Rotate270 => Point::new(y, height - 1 - x),
+        }
+    }
+}
+
+impl<S: Screen> OriginDimensions for Rotated<S> {
+    fn size(&self) -> Size {
+        self.current.rotation.size(self.screen.bounding_box().size)
+    }
+}
+
+impl<S: Screen> DrawTarget for Rotated<S> {
+    type Color = BinaryColor;
+    type Error = S::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (orientation, panel) = (self.current, self.screen.bounding_box().size);
+        self.screen.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(orientation.to_panel(panel, point), color)),
+        )
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.screen.clear(color)
+    }
+}
+
+impl<S: Screen> Screen for Rotated<S> {
+    fn flush(&mut self) -> Result<()> {
+        self.screen.flush()?;
+        let orientation = *self.orientation.lock().unwrap();
+        if orientation != self.current {
+            // What is on the screen was drawn the old way round
+            self.current = orientation;
+            self.screen.clear(BinaryColor::Off).unwrap();
+            self.screen.flush()?;
+        }
+        Ok(())
+    }
+
+    fn delay(&mut self, duration: Duration) {
+        self.screen.delay(duration);
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<()> {
+        self.screen.set_contrast(contrast)
+    }
+
+    fn set_on(&mut self, on: bool) -> Result<()> {
+        self.screen.set_on(on)
+    }
+}
+
+/// An in-memory 1bpp framebuffer the size of the OLED.
+pub struct Framebuffer {
+    size: Size,
+    /// Rows of pixels, top to bottom.
+    pixels: Vec<bool>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::new(Panel::default().size)
+    }
+}
+
+impl Framebuffer {
+    pub fn new(size: Size) -> Self {
+        Framebuffer {
+            size,
+            pixels: vec![false; (size.width * size.height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        self.pixels[(y * self.size.width + x) as usize]
+    }
+
+    /// Render the buffer as text, packing two rows of pixels into every line
+    /// using half block characters.
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        let width = self.size.width as usize;
+        for rows in self.pixels.chunks(width * 2) {
+            for x in 0..width {
+                let top = rows[x];
+                let bottom = rows.get(width + x).is_some_and(|p| *p);
+                out.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) {
+                if x < self.size.width && y < self.size.height {
+                    self.pixels[(y * self.size.width + x) as usize] = color.is_on();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Screen for Framebuffer {
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A screen that draws nothing and doesn't wait, but keeps track of how long
+/// the effects drawn on it would have taken.
+///
+/// Used to run the display logic in simulated time.
+pub struct HeadlessScreen {
+    pub size: Size,
+    pub elapsed: Duration,
+}
+
+impl Default for HeadlessScreen {
+    fn default() -> Self {
+        HeadlessScreen {
+            size: Panel::default().size,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl OriginDimensions for HeadlessScreen {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for HeadlessScreen {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        Ok(())
+    }
+}
+
+impl Screen for HeadlessScreen {
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn delay(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panels() {
+        assert_eq!("ssd1306-128x32".parse(), Ok(Panel::default()));
+        assert_eq!(
+            "sh1106-128x64-spi".parse(),
+            Ok(Panel {
+                controller: Controller::Sh1106,
+                size: Size::new(128, 64),
+                interface: Interface::Spi,
+            })
+        );
+        assert!("sh1106-128x32".parse::<Panel>().is_err());
+        assert!("ssd1306".parse::<Panel>().is_err());
+    }
+
+    /// The panel pixels that are lit after drawing a pixel at `point`.
+    fn lit(orientation: Orientation, point: Point) -> Vec<(u32, u32)> {
+        let mut display = Rotated::new(Framebuffer::default(), Arc::new(Mutex::new(orientation)));
+        Pixel(point, BinaryColor::On).draw(&mut display).unwrap();
+        (0..32)
+            .flat_map(|y| (0..128).map(move |x| (x, y)))
+            .filter(|(x, y)| display.inner().pixel(*x, *y))
+            .collect()
+    }
+
+    #[test]
+    fn rotations() {
+        let rotated = |rotation| Orientation {
+            rotation,
+            mirrored: false,
+        };
+        let corner = Point::new(1, 0);
+        assert_eq!(lit(rotated(Rotation::Rotate0), corner), vec![(1, 0)]);
+        assert_eq!(lit(rotated(Rotation::Rotate90), corner), vec![(127, 1)]);
+        assert_eq!(lit(rotated(Rotation::Rotate180), corner), vec![(126, 31)]);
+        assert_eq!(lit(rotated(Rotation::Rotate270), corner), vec![(0, 30)]);
+        let mirrored = Orientation {
+            rotation: Rotation::Rotate0,
+            mirrored: true,
+        };
+        assert_eq!(lit(mirrored, corner), vec![(126, 0)]);
+        // Off the turned screen
+        assert!(lit(rotated(Rotation::Rotate90), Point::new(40, 0)).is_empty());
+
+        let orientation = Arc::new(Mutex::new(Orientation::default()));
+        let mut display = Rotated::new(Framebuffer::default(), orientation.clone());
+        assert_eq!(display.size(), Size::new(128, 32));
+        orientation.lock().unwrap().rotation = Rotation::Rotate270;
+        display.flush().unwrap();
+        assert_eq!(display.size(), Size::new(32, 128));
+
+        assert_eq!("270".parse(), Ok(Rotation::Rotate270));
+        assert!("45".parse::<Rotation>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! How a badge picks the poems it broadcasts, and the random poems it shows
+//! when nothing was received.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::corpus;
+use crate::library::Library;
+
+/// Weight of a poem without a rating or a weighted tag.
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Any poem, every time.
+    Uniform,
+    /// Every poem once, in a random order, before any of them repeats.
+    #[default]
+    ShuffleBag,
+    /// Some poems more often than others.
+    Weighted(Weights),
+    /// The poem that was heard or picked the longest ago, so poems spread
+    /// through the parade evenly.
+    LeastRecentlySeen,
+}
+
+/// Weights of poems, by id or by the tags in the corpus.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Weights {
+    pub tags: BTreeMap<String, u32>,
+    /// Weights of single poems, which win over the weights of their tags.
+    pub ratings: BTreeMap<u8, u32>,
+}
+
+impl Weights {
+    /// The rating of a poem, or else the highest weight of its tags.
+    pub fn weight(&self, id: u8) -> u32 {
+        if let Some(rating) = self.ratings.get(&id) {
+            return *rating;
+        }
+        corpus::get(id)
+            .and_then(|entry| {
+                entry
+                    .tags
+                    .iter()
+                    .filter_map(|tag| self.tags.get(*tag))
+                    .max()
+                    .copied()
+            })
+            .unwrap_or(DEFAULT_WEIGHT)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidStrategy(pub String);
+
+impl fmt::Display for InvalidStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid strategy {:?}, expected uniform, shuffle, least-seen or \
+             weighted:<tag or poem id>=<weight>,..",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidStrategy {}
+
+/// Parses `uniform`, `shuffle`, `least-seen` or weights like
+/// `weighted:classic=5,12=3`, that make poems tagged "classic" and poem 12
+/// more likely.
+impl FromStr for Strategy {
+    type Err = InvalidStrategy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidStrategy(s.to_string());
+        match s.trim() {
+            "uniform" => return Ok(Strategy::Uniform),
+            "shuffle" => return Ok(Strategy::ShuffleBag),
+            "least-seen" => return Ok(Strategy::LeastRecentlySeen),
+            _ => {}
+        }
+        let weights = s.trim().strip_prefix("weighted:").ok_or_else(invalid)?;
+        let mut parsed = Weights::default();
+        for weight in weights.split(',') {
+            let (key, value) = weight.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim().parse().map_err(|_| invalid())?;
+            match key.trim().parse::<u8>() {
+                Ok(id) => parsed.ratings.insert(id, value),
+                Err(_) => parsed.tags.insert(key.trim().to_string(), value),
+            };
+        }
+        Ok(Strategy::Weighted(parsed))
+    }
+}
+
+/// Picks poems from a library with a `Strategy`.
+pub struct Selector {
+    strategy: Strategy,
+    /// Poems that weren't picked from the current shuffle bag.
+    bag: BTreeSet<u8>,
+    /// When poems were last seen, counted in sightings.
+    seen: BTreeMap<u8, u64>,
+    sightings: u64,
+}
+
+impl Selector {
+    pub fn new(strategy: Strategy) -> Self {
+        Selector {
+            strategy,
+            bag: BTreeSet::new(),
+            seen: BTreeMap::new(),
+            sightings: 0,
+        }
+    }
+
+    pub fn strategy(&self) -> &Strategy {
+        &self.strategy
+    }
+
+    /// Remember that a poem was heard from another badge.
+    pub fn seen(&mut self, id: u8) {
+        self.sightings += 1;
+        self.seen.insert(id, self.sightings);
+    }
+
+    /// Pick a poem from `library` that isn't skipped, `None` if there is none.
+    pub fn select<R: Rng>(
+        &mut self,
+        library: &Library,
+        rng: &mut R,
+        skip: impl Fn(u8) -> bool,
+    ) -> Option<u8> {
+        let candidates: Vec<u8> = library
+            .iter()
+            .map(|(id, _)| id)
+            .filter(|id| !skip(*id))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let id = match &self.strategy {
+            Strategy::Uniform => candidates[rng.gen_range(0..candidates.len())],
+            Strategy::ShuffleBag => {
+                self.bag.retain(|id| library.get(*id).is_some());
+                if self.bag.is_empty() {
+                    self.bag = library.iter().map(|(id, _)| id).collect();
+                }
+                let mut left: Vec<u8> = self.bag.iter().copied().filter(|id| !skip(*id)).collect();
+                // Draw a skipped poem rather than refill the bag early, so
+                // every poem still comes up once per round
+                if left.is_empty() {
+                    left = self.bag.iter().copied().collect();
+                }
+                let id = left[rng.gen_range(0..left.len())];
+                self.bag.remove(&id);
+                id
+            }
+            Strategy::Weighted(weights) => {
+                match WeightedIndex::new(candidates.iter().map(|id| weights.weight(*id))) {
+                    Ok(index) => candidates[index.sample(rng)],
+                    // All weights are 0
+                    Err(_) => candidates[rng.gen_range(0..candidates.len())],
+                }
+            }
+            Strategy::LeastRecentlySeen => {
+                // Poems that were never seen come first
+                let oldest = candidates.iter().map(|id| self.seen.get(id)).min()?;
+                let oldest: Vec<u8> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|id| self.seen.get(id) == oldest)
+                    .collect();
+                oldest[rng.gen_range(0..oldest.len())]
+            }
+        };
+        self.seen(id);
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn library(n: u8) -> Library {
+        Library::new((0..n).map(|id| (id, id.to_string())).collect())
+    }
+
+    fn picks(strategy: Strategy, library: &Library, n: usize) -> Vec<u8> {
+        let mut selector = Selector::new(strategy);
+        let rng = &mut StdRng::seed_from_u64(42);
+        (0..n)
+            .map(|_| selector.select(library, rng, |_| false).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn shuffle_bag() {
+        let library = library(5);
+        let picks = picks(Strategy::ShuffleBag, &library, 10);
+        for bag in picks.chunks(5) {
+            let mut bag = bag.to_vec();
+            bag.sort();
+            assert_eq!(bag, vec![0, 1, 2, 3, 4]);
+        }
+        // The same seed picks the same poems
+        assert_eq!(picks, self::picks(Strategy::ShuffleBag, &library, 10));
+    }
+
+    #[test]
+    fn shuffle_bag_with_skips() {
+        let library = library(5);
+        let mut selector = Selector::new(Strategy::ShuffleBag);
+        let rng = &mut StdRng::seed_from_u64(42);
+        let mut drawn: Vec<u8> = (0..3)
+            .map(|_| selector.select(&library, rng, |_| false).unwrap())
+            .collect();
+
+        // Everything left in the bag is skipped, one of them is drawn anyway
+        let left: Vec<u8> = (0..5).filter(|id| !drawn.contains(id)).collect();
+        let id = selector.select(&library, rng, |id| left.contains(&id));
+        assert!(left.contains(&id.unwrap()));
+        drawn.extend(id);
+        // Skipping a poem that was drawn doesn't refill the bag
+        let first = drawn[0];
+        drawn.extend(selector.select(&library, rng, |id| id == first));
+        drawn.sort();
+        assert_eq!(drawn, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn weighted() {
+        let library = library(3);
+        let strategy: Strategy = "weighted:1=0,2=0".parse().unwrap();
+        assert!(picks(strategy, &library, 10).iter().all(|id| *id == 0));
+
+        // Poem 0 is the only classic
+        let strategy: Strategy = "weighted:classic=100".parse().unwrap();
+        let picks = picks(strategy, &library, 100);
+        assert!(picks.iter().filter(|id| **id == 0).count() > 80);
+
+        assert!("weighted:classic".parse::<Strategy>().is_err());
+        assert!("random".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn least_recently_seen() {
+        let library = library(4);
+        let mut selector = Selector::new(Strategy::LeastRecentlySeen);
+        let rng = &mut StdRng::seed_from_u64(42);
+        selector.seen(2);
+        selector.seen(0);
+        selector.seen(3);
+        assert_eq!(selector.select(&library, rng, |_| false), Some(1));
+        assert_eq!(selector.select(&library, rng, |_| false), Some(2));
+        assert_eq!(selector.select(&library, rng, |id| id == 0), Some(3));
+        assert_eq!(selector.select(&library, rng, |_| true), None);
+    }
+}
@@ -0,0 +1,335 @@
+//! Parser for the commands of the serial console.
+use std::fmt;
+
+use crate::identity::{DeviceId, InvalidDeviceId};
+use crate::power::{InvalidProfile, Profile};
+use crate::protocol::MAX_TEXT_LEN;
+use crate::screen::{InvalidRotation, Rotation};
+
+pub const HELP: &str = "\
+Commands:
+  status          device id, counters and uptime
+  send <poem_id>  broadcast a poem now
+  share <poem_id> broadcast the text of a poem, for badges that don't have it
+  show <poem_id>  show a poem on the display
+  list            list all poems
+  add <text>      add a poem to the library, \\n starts a new line
+  remove <poem_id> remove a poem from the library
+  peers           list badges that were heard recently
+  set id <n>      change the device id (applied after a reboot)
+  set name <name> change the device name
+  set rotation <0|90|180|270> turn what is drawn clockwise
+  set mirror <on|off> flip what is drawn left to right
+  set power <parade|storage> change the power profile (applied after a reboot)
+  reboot          restart the badge
+  help            show this help";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Status,
+    Send(u8),
+    Share(u8),
+    Show(u8),
+    List,
+    Add(String),
+    Remove(u8),
+    Peers,
+    SetId(DeviceId),
+    SetName(String),
+    SetRotation(Rotation),
+    SetMirror(bool),
+    SetPower(Profile),
+    Reboot,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    Unknown(String),
+    MissingArgument(&'static str),
+    UnexpectedArgument(String),
+    InvalidPoemId(String),
+    InvalidDeviceId(InvalidDeviceId),
+    InvalidRotation(InvalidRotation),
+    InvalidSwitch(String),
+    InvalidProfile(InvalidProfile),
+    /// A poem of this many bytes is too long to send.
+    PoemTooLong(usize),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "empty command"),
+            CommandError::Unknown(c) => write!(f, "unknown command {:?}, try \"help\"", c),
+            CommandError::MissingArgument(a) => write!(f, "missing argument <{}>", a),
+            CommandError::UnexpectedArgument(a) => write!(f, "unexpected argument {:?}", a),
+            CommandError::InvalidPoemId(id) => write!(f, "invalid poem id {:?}", id),
+            CommandError::InvalidDeviceId(e) => e.fmt(f),
+            CommandError::InvalidRotation(e) => e.fmt(f),
+            CommandError::InvalidSwitch(s) => write!(f, "expected on or off, got {:?}", s),
+            CommandError::InvalidProfile(e) => e.fmt(f),
+            CommandError::PoemTooLong(len) => write!(
+                f,
+                "poem is {} bytes, at most {} can be sent",
+                len, MAX_TEXT_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Parse a line typed on the console.
+pub fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::Empty)?;
+
+    let command = match command {
+        "help" | "?" => Command::Help,
+        "status" => Command::Status,
+        "send" => Command::Send(poem_id(words.next())?),
+        "share" => Command::Share(poem_id(words.next())?),
+        "show" => Command::Show(poem_id(words.next())?),
+        "list" => Command::List,
+        "add" => {
+            let text = words.collect::<Vec<_>>().join(" ").replace("\\n", "\n");
+            if text.is_empty() {
+                return Err(CommandError::MissingArgument("text"));
+            }
+            if text.len() > MAX_TEXT_LEN {
+                return Err(CommandError::PoemTooLong(text.len()));
+            }
+            return Ok(Command::Add(text));
+        }
+        "remove" => Command::Remove(poem_id(words.next())?),
+        "peers" => Command::Peers,
+        "reboot" => Command::Reboot,
+        "set" => match words.next() {
+            Some("id") => Command::SetId(
+                words
+                    .next()
+                    .ok_or(CommandError::MissingArgument("n"))?
+                    .parse()
+                    .map_err(CommandError::InvalidDeviceId)?,
+            ),
+            Some("name") => {
+                // Names can contain spaces
+                let name = words.collect::<Vec<_>>().join(" ");
+                if name.is_empty() {
+                    return Err(CommandError::MissingArgument("name"));
+                }
+                return Ok(Command::SetName(name));
+            }
+            Some("rotation") => Command::SetRotation(
+                words
+                    .next()
+                    .ok_or(CommandError::MissingArgument("degrees"))?
+                    .parse()
+                    .map_err(CommandError::InvalidRotation)?,
+            ),
+            Some("mirror") => match words.next() {
+                Some("on") => Command::SetMirror(true),
+                Some("off") => Command::SetMirror(false),
+                Some(other) => return Err(CommandError::InvalidSwitch(other.to_string())),
+                None => return Err(CommandError::MissingArgument("on|off")),
+            },
+            Some("power") => Command::SetPower(
+                words
+                    .next()
+                    .ok_or(CommandError::MissingArgument("profile"))?
+                    .parse()
+                    .map_err(CommandError::InvalidProfile)?,
+            ),
+            Some(other) => return Err(CommandError::Unknown(format!("set {}", other))),
+            None => {
+                return Err(CommandError::MissingArgument(
+                    "id|name|rotation|mirror|power",
+                ))
+            }
+        },
+        other => return Err(CommandError::Unknown(other.to_string())),
+    };
+
+    match words.next() {
+        Some(extra) => Err(CommandError::UnexpectedArgument(extra.to_string())),
+        None => Ok(command),
+    }
+}
+
+fn poem_id(arg: Option<&str>) -> Result<u8, CommandError> {
+    let arg = arg.ok_or(CommandError::MissingArgument("poem_id"))?;
+    arg.parse()
+        .map_err(|_| CommandError::InvalidPoemId(arg.to_string()))
+}
+
+/// A line that can't be used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineError {
+    TooLong,
+    NotUtf8,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::TooLong => write!(f, "line is longer than {} bytes", MAX_LINE_LEN),
+            LineError::NotUtf8 => write!(f, "line isn't valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Collects bytes from the UART into lines.
+#[derive(Default)]
+pub struct LineBuffer {
+    line: Vec<u8>,
+    /// Bytes were dropped because the line got too long.
+    overflow: bool,
+}
+
+/// Longest line that is buffered, room for `add` with the longest poem that
+/// can be sent and its escaped newlines.
+const MAX_LINE_LEN: usize = MAX_TEXT_LEN + MAX_TEXT_LEN / 8;
+
+impl LineBuffer {
+    /// Feed a byte, returning the line once it's complete. A line that was
+    /// too long is an error, rather than cut short.
+    pub fn push(&mut self, byte: u8) -> Option<Result<String, LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let line = std::mem::take(&mut self.line);
+                if std::mem::take(&mut self.overflow) {
+                    return Some(Err(LineError::TooLong));
+                }
+                if line.is_empty() {
+                    return None;
+                }
+                Some(String::from_utf8(line).map_err(|_| LineError::NotUtf8))
+            }
+            // Backspace and delete remove a whole character
+            0x08 | 0x7f => {
+                while let Some(b) = self.line.pop() {
+                    if b & 0xc0 != 0x80 {
+                        break;
+                    }
+                }
+                None
+            }
+            b if b.is_ascii_control() => None,
+            _ if self.line.len() == MAX_LINE_LEN => {
+                self.overflow = true;
+                None
+            }
+            b => {
+                self.line.push(b);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(parse("  list  "), Ok(Command::List));
+        assert_eq!(parse("send 3"), Ok(Command::Send(3)));
+        assert_eq!(parse("show 41"), Ok(Command::Show(41)));
+        assert_eq!(parse("share 0"), Ok(Command::Share(0)));
+        assert_eq!(parse("peers"), Ok(Command::Peers));
+        assert_eq!(
+            parse(r"add Oh freddled gruntbuggly\nthy micturations"),
+            Ok(Command::Add(
+                "Oh freddled gruntbuggly\nthy micturations".to_string()
+            ))
+        );
+        assert_eq!(parse("remove 7"), Ok(Command::Remove(7)));
+        assert_eq!(parse("reboot"), Ok(Command::Reboot));
+        assert_eq!(
+            parse("set id 12"),
+            Ok(Command::SetId(DeviceId::new(12).unwrap()))
+        );
+        assert_eq!(
+            parse("set name Prostetnic Vogon Jeltz"),
+            Ok(Command::SetName("Prostetnic Vogon Jeltz".to_string()))
+        );
+        assert_eq!(
+            parse("set rotation 180"),
+            Ok(Command::SetRotation(Rotation::Rotate180))
+        );
+        assert_eq!(parse("set mirror on"), Ok(Command::SetMirror(true)));
+        assert_eq!(
+            parse("set power storage"),
+            Ok(Command::SetPower(Profile::Storage))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), Err(CommandError::Empty));
+        assert_eq!(parse("recite"), Err(CommandError::Unknown("recite".into())));
+        assert_eq!(parse("send"), Err(CommandError::MissingArgument("poem_id")));
+        assert_eq!(parse("add "), Err(CommandError::MissingArgument("text")));
+        assert_eq!(
+            parse("send x"),
+            Err(CommandError::InvalidPoemId("x".into()))
+        );
+        assert_eq!(
+            parse("show 300"),
+            Err(CommandError::InvalidPoemId("300".into()))
+        );
+        assert_eq!(
+            parse("status now"),
+            Err(CommandError::UnexpectedArgument("now".into()))
+        );
+        assert!(matches!(
+            parse("set id 0"),
+            Err(CommandError::InvalidDeviceId(_))
+        ));
+        assert!(matches!(
+            parse("set rotation 45"),
+            Err(CommandError::InvalidRotation(_))
+        ));
+        assert_eq!(
+            parse("set mirror yes"),
+            Err(CommandError::InvalidSwitch("yes".into()))
+        );
+        assert_eq!(
+            parse("set colour"),
+            Err(CommandError::Unknown("set colour".into()))
+        );
+    }
+
+    #[test]
+    fn line_buffer() {
+        let mut buffer = LineBuffer::default();
+        let mut lines = vec![];
+        for b in "\r\nsen\x7fnd 1\r\nadd Jeltz\u{e9}\x7f\u{e8}\n".bytes() {
+            lines.extend(buffer.push(b));
+        }
+        assert_eq!(
+            lines,
+            vec![Ok("send 1".into()), Ok("add Jeltz\u{e8}".into())]
+        );
+
+        // Too long lines aren't cut short
+        let poem = format!("add {}\n", "x".repeat(MAX_LINE_LEN));
+        let lines: Vec<_> = poem.bytes().filter_map(|b| buffer.push(b)).collect();
+        assert_eq!(lines, vec![Err(LineError::TooLong)]);
+        assert_eq!(buffer.push(0xff), None);
+        assert_eq!(buffer.push(b'\n'), Some(Err(LineError::NotUtf8)));
+
+        let poem = format!("add {}", "x".repeat(MAX_TEXT_LEN));
+        assert_eq!(parse(&poem), Ok(Command::Add("x".repeat(MAX_TEXT_LEN))));
+        assert_eq!(
+            parse(&format!("{}x", poem)),
+            Err(CommandError::PoemTooLong(MAX_TEXT_LEN + 1))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Marquees: text that scrolls through part of the screen.
+//!
+//! A `Ticker` moves a single line of any length from right to left, like a
+//! news ticker, and a `Scroller` moves a block of text up. Both are
+//! animations that only redraw their own area, so the rest of the screen
+//! stays as it is.
+use std::time::Duration;
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::renderer::TextRenderer;
+use embedded_graphics::text::{Baseline, Text};
+
+use crate::animation::{Animation, Status};
+use crate::font::{character_style, text_style};
+use crate::glyphs;
+use crate::layout::Layout;
+use crate::screen::Screen;
+
+/// Pixels text moves per frame by default.
+pub const DEFAULT_SPEED: u32 = 1;
+
+/// A single line that scrolls through `area` from right to left, unless it
+/// fits.
+pub struct Ticker {
+    text: String,
+    area: Rectangle,
+    /// Width of the text in pixels.
+    width: u32,
+    speed: u32,
+    /// Number of times the text scrolls by, `None` to keep going until it is
+    /// cancelled.
+    passes: Option<u32>,
+    /// Pixels the text moved in this pass.
+    offset: u32,
+}
+
+impl Ticker {
+    pub fn new(text: &str, area: Rectangle) -> Self {
+        let text = glyphs::normalize(&text.replace('\n', " ")).into_owned();
+        let width = character_style()
+            .measure_string(&text, Point::zero(), Baseline::Top)
+            .next_position
+            .x
+            .max(0) as u32;
+        Ticker {
+            text,
+            area,
+            width,
+            speed: DEFAULT_SPEED,
+            passes: Some(1),
+            offset: 0,
+        }
+    }
+
+    /// Move the text this many pixels per frame.
+    pub fn speed(mut self, pixels: u32) -> Self {
+        self.speed = pixels.max(1);
+        self
+    }
+
+    /// Scroll the text by this many times.
+    pub fn passes(mut self, passes: u32) -> Self {
+        self.passes = Some(passes.max(1));
+        self
+    }
+
+    /// Keep scrolling until cancelled.
+    pub fn forever(mut self) -> Self {
+        self.passes = None;
+        self
+    }
+
+    /// Whether the text fits in the area, so it doesn't have to scroll.
+    pub fn fits(&self) -> bool {
+        self.width <= self.area.size.width
+    }
+}
+
+impl<S: Screen> Animation<S> for Ticker {
+    fn frame(&mut self, display: &mut S, _dt: Duration) -> Status {
+        let mut area = display.clipped(&self.area);
+        area.fill_solid(&self.area, BinaryColor::Off).unwrap();
+        if self.fits() {
+            Text::with_baseline(
+                &self.text,
+                self.area.top_left,
+                character_style(),
+                Baseline::Top,
+            )
+            .draw(&mut area)
+            .unwrap();
+            return Status::Done;
+        }
+
+        // Enter on the right, leave on the left
+        let x = self.area.top_left.x + self.area.size.width as i32 - self.offset as i32;
+        Text::with_baseline(
+            &self.text,
+            Point::new(x, self.area.top_left.y),
+            character_style(),
+            Baseline::Top,
+        )
+        .draw(&mut area)
+        .unwrap();
+
+        self.offset += self.speed;
+        if self.offset < self.area.size.width + self.width {
+            return Status::Running;
+        }
+        self.offset = 0;
+        match &mut self.passes {
+            Some(1) => Status::Done,
+            Some(passes) => {
+                *passes -= 1;
+                Status::Running
+            }
+            None => Status::Running,
+        }
+    }
+}
+
+/// A block of text of any length that enters `area` at the bottom and
+/// scrolls up until the last line has left it at the top.
+pub struct Scroller {
+    layout: Layout,
+    area: Rectangle,
+    speed: u32,
+    /// Pixels the text moved.
+    offset: u32,
+}
+
+impl Scroller {
+    /// Wrap `text` to the width of `area`.
+    pub fn new(text: &str, area: Rectangle) -> Self {
+        Scroller {
+            layout: Layout::new(
+                &character_style(),
+                &text_style(),
+                &glyphs::normalize(text),
+                area.size.width,
+            ),
+            area,
+            speed: DEFAULT_SPEED,
+            offset: 0,
+        }
+    }
+
+    /// Move the text this many pixels per frame.
+    pub fn speed(mut self, pixels: u32) -> Self {
+        self.speed = pixels.max(1);
+        self
+    }
+}
+
+impl<S: Screen> Animation<S> for Scroller {
+    fn frame(&mut self, display: &mut S, _dt: Duration) -> Status {
+        let mut area = display.clipped(&self.area);
+        area.fill_solid(&self.area, BinaryColor::Off).unwrap();
+
+        let top = self.area.top_left.y + self.area.size.height as i32 - self.offset as i32;
+        let bottom = self.area.top_left.y + self.area.size.height as i32;
+        let line_height = self.layout.line_height as i32;
+        for (i, line) in self.layout.lines.iter().enumerate() {
+            let y = top + i as i32 * line_height;
+            // Only draw the lines that are in the area
+            if y + line_height <= self.area.top_left.y || y >= bottom {
+                continue;
+            }
+            Text::with_baseline(
+                &line.text,
+                Point::new(self.area.top_left.x, y),
+                character_style(),
+                Baseline::Top,
+            )
+            .draw(&mut area)
+            .unwrap();
+        }
+
+        self.offset += self.speed;
+        if self.offset < self.area.size.height + self.layout.height() {
+            Status::Running
+        } else {
+            Status::Done
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{play, Player};
+    use crate::screen::{Framebuffer, HeadlessScreen};
+
+    /// The bottom line of the screen, 8 pixels high.
+    fn bottom_line() -> Rectangle {
+        Rectangle::new(Point::new(0, 24), Size::new(128, 8))
+    }
+
+    fn lit_in(display: &Framebuffer, area: Rectangle) -> usize {
+        area.points()
+            .filter(|p| display.pixel(p.x as u32, p.y as u32))
+            .count()
+    }
+
+    #[test]
+    fn ticker_scrolls_long_lines() {
+        let text = "Oh freddled gruntbuggly, thy micturations are to me";
+        let ticker = Ticker::new(text, bottom_line()).speed(2);
+        assert!(!ticker.fits());
+
+        // In from the right, out to the left, 2 pixels per frame
+        let mut display = HeadlessScreen::default();
+        play(&mut display, ticker).unwrap();
+        let frames = (128 + text.len() as u32 * 5).div_ceil(2);
+        assert_eq!(display.elapsed, Duration::from_millis(40) * (frames - 1));
+
+        let mut display = HeadlessScreen::default();
+        play(&mut display, Ticker::new(text, bottom_line()).passes(2)).unwrap();
+        let frames = 128 + text.len() as u32 * 5;
+        assert_eq!(
+            display.elapsed,
+            Duration::from_millis(40) * (2 * frames - 1)
+        );
+    }
+
+    #[test]
+    fn ticker_only_redraws_its_area() {
+        let mut display = Framebuffer::default();
+        let above = Rectangle::new(Point::zero(), Size::new(128, 24));
+        display.fill_solid(&above, BinaryColor::On).unwrap();
+
+        let mut ticker = Ticker::new(
+            "Groop, I implore thee, my foonting turlingdromes",
+            bottom_line(),
+        )
+        .forever();
+        let mut frames = 0;
+        Player::default()
+            .play(&mut display, &mut ticker, || {
+                frames += 1;
+                frames > 30
+            })
+            .unwrap();
+        assert_eq!(lit_in(&display, above), 128 * 24);
+        // The start of the text entered on the right
+        let right = Rectangle::new(Point::new(98, 24), Size::new(30, 8));
+        assert!(lit_in(&display, right) > 0);
+        assert_eq!(
+            lit_in(
+                &display,
+                Rectangle::new(Point::new(0, 24), Size::new(98, 8))
+            ),
+            0
+        );
+
+        // Short text doesn't move
+        let mut display = Framebuffer::default();
+        play(&mut display, Ticker::new("Groop", bottom_line())).unwrap();
+        assert!(
+            lit_in(
+                &display,
+                Rectangle::new(Point::new(0, 24), Size::new(25, 8))
+            ) > 0
+        );
+    }
+
+    #[test]
+    fn scroller_moves_text_up() {
+        let area = Rectangle::new(Point::new(0, 8), Size::new(128, 16));
+        let text = "Oh freddled gruntbuggly\nthy micturations\nare to me\nas plurdled gabble";
+        let scroller = Scroller::new(text, area).speed(3);
+        // 4 lines of 7 pixels, starting below the area
+        let frames = (16 + 4 * 7_u32).div_ceil(3);
+        let mut display = HeadlessScreen::default();
+        play(&mut display, scroller).unwrap();
+        assert_eq!(display.elapsed, Duration::from_millis(40) * (frames - 1));
+
+        let mut display = Framebuffer::default();
+        let mut scroller = Scroller::new(text, area);
+        let mut frames = 0;
+        Player::default()
+            .play(&mut display, &mut scroller, || {
+                frames += 1;
+                frames > 12
+            })
+            .unwrap();
+        // The first line is in the area, and nothing outside of it is drawn
+        assert!(lit_in(&display, area) > 0);
+        assert_eq!(
+            lit_in(&display, Rectangle::new(Point::zero(), Size::new(128, 8))),
+            0
+        );
+        assert_eq!(
+            lit_in(
+                &display,
+                Rectangle::new(Point::new(0, 24), Size::new(128, 8))
+            ),
+            0
+        );
+    }
+}
@@ -0,0 +1,1187 @@
+//! The send, receive and display logic of a badge, independent of the radio
+//! and the panel it runs on.
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result};
+use embedded_graphics::image::Image;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Baseline, LineHeight, Text, TextStyle, TextStyleBuilder};
+use rand::Rng;
+
+use crate::animation::{self, Pause, Player, Sequence};
+use crate::assets::LOGOTYPE;
+use crate::battery::Level;
+use crate::corpus;
+use crate::effects;
+use crate::font::{character_style, FONT};
+use crate::fragments::Reassembler;
+use crate::glyphs;
+use crate::identity::DeviceId;
+use crate::layout::Layout;
+use crate::library::Library;
+use crate::peers::{Mac, Peers};
+use crate::playback::{History, Policy, Priority, Queue};
+use crate::protection::{Mode, Protection, ScreenSaver};
+use crate::protocol::{self, Message, Packet};
+use crate::relay::Seen;
+use crate::screen::Screen;
+use crate::selector::{Selector, Strategy};
+use crate::text::{Scroller, Ticker};
+use crate::utils::mac_to_string;
+use crate::utils::screen_center;
+use crate::TOTAL_DEVICES;
+
+pub const SEND_DELAY_RANGE: std::ops::Range<u64> = 5..20;
+/// How often a badge broadcasts its status.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the status screen is shown before checking for new poems.
+const STATUS_DURATION: Duration = Duration::from_secs(4);
+/// How long to wait for a poem after showing the status screen. Poems that
+/// arrive while the status screen is shown cut it short.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Pick a random poem if nothing was received for this long.
+const FALLBACK_AFTER: Duration = Duration::from_secs(10);
+
+/// The text of a poem, or where to find it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PoemText {
+    /// Id in the library of the badge.
+    Library(u8),
+    /// A poem that was sent as text and isn't in the library.
+    Received(String),
+}
+
+pub struct Poem {
+    pub text: PoemText,
+    /// Device id of the badge the poem came from.
+    pub src: u8,
+    /// Sequence number of the original packet.
+    pub seq: u16,
+    /// Number of badges that relayed the poem.
+    pub hops: u8,
+    /// How many more times the poem may be relayed.
+    pub ttl: u8,
+}
+
+impl Poem {
+    /// A poem that didn't come in over the radio.
+    pub fn local(id: u8, src: u8) -> Self {
+        Poem {
+            text: PoemText::Library(id),
+            src,
+            seq: 0,
+            hops: 0,
+            ttl: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Counters {
+    pub received: u32,
+    pub sent: u32,
+    /// Frames that aren't valid poem packets, either from other ESP-NOW devices
+    /// on the channel or damaged in transit.
+    pub dropped: u32,
+    /// Times a device id was heard from two different MAC addresses.
+    pub collisions: u32,
+    /// Poems that were received before, directly or through a relay.
+    pub duplicates: u32,
+    pub relayed: u32,
+}
+
+/// Another badge that uses our device id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub device_id: DeviceId,
+    pub mac: Mac,
+    /// The id this badge should switch to, if it resolves the collision.
+    pub renumber: Option<DeviceId>,
+}
+
+/// State of a badge that is shared between the radio, display and console.
+pub struct Badge {
+    pub device_id: DeviceId,
+    pub mac: Mac,
+    pub library: Mutex<Library>,
+    pub counters: Mutex<Counters>,
+    pub peers: Mutex<Peers>,
+    /// Pick a new id when another badge uses ours. Of the two badges, the one
+    /// with the highest MAC address switches.
+    pub auto_resolve: bool,
+    /// Relay received poems, which then make at most this many hops.
+    pub relay_ttl: Option<u8>,
+    /// Add poems that were received as text to the library.
+    pub store_received: bool,
+    /// The last battery reading, `None` if the battery isn't measured.
+    pub battery: Mutex<Option<Level>>,
+    collision: Mutex<Option<Collision>>,
+    seen: Mutex<Seen>,
+    fragments: Mutex<Reassembler>,
+}
+
+impl Badge {
+    /// Peers that weren't heard from for `peer_timeout` are forgotten.
+    pub fn new(device_id: DeviceId, mac: Mac, library: Library, peer_timeout: Duration) -> Self {
+        Badge {
+            device_id,
+            mac,
+            library: Mutex::new(library),
+            counters: Mutex::new(Counters::default()),
+            peers: Mutex::new(Peers::new(peer_timeout)),
+            auto_resolve: false,
+            relay_ttl: None,
+            store_received: false,
+            battery: Mutex::new(None),
+            collision: Mutex::new(None),
+            seen: Mutex::new(Seen::default()),
+            fragments: Mutex::new(Reassembler::default()),
+        }
+    }
+
+    /// The last collision with our device id, if it wasn't taken yet.
+    pub fn take_collision(&self) -> Option<Collision> {
+        self.collision.lock().unwrap().take()
+    }
+
+    /// Handle a frame from the radio, returning the poem it carries unless
+    /// it was received before.
+    ///
+    /// `now` is the time since boot, `rssi` the signal strength of the frame
+    /// if the radio reports it.
+    pub fn receive(&self, mac: Mac, rssi: Option<i8>, data: &[u8], now: Duration) -> Option<Poem> {
+        let packet = {
+            let mut c = self.counters.lock().unwrap();
+            match Packet::parse(data) {
+                Ok(packet) => {
+                    c.received += 1;
+                    packet
+                }
+                Err(e) => {
+                    c.dropped += 1;
+                    log::warn!(
+                        "Dropping {} frame: {} ({} dropped)",
+                        if e.is_foreign() {
+                            "foreign"
+                        } else {
+                            "malformed"
+                        },
+                        e,
+                        c.dropped
+                    );
+                    return None;
+                }
+            }
+        };
+        let mut peers = self.peers.lock().unwrap();
+        let collision = if peers.seen(mac, &packet, rssi, now) {
+            self.check_collision(&peers, mac, packet.src)
+        } else {
+            None
+        };
+        // The display thread and the console lock the counters before the
+        // peers, so don't hold on to the peers while taking the counters
+        drop(peers);
+        if let Some(own) = collision {
+            self.counters.lock().unwrap().collisions += 1;
+            if own.is_some() {
+                *self.collision.lock().unwrap() = own;
+            }
+        }
+
+        let poem = match packet.message {
+            Message::Poem { id } => Poem {
+                text: PoemText::Library(id),
+                src: packet.src,
+                seq: packet.seq,
+                hops: 0,
+                ttl: self.relay_ttl.unwrap_or(0),
+            },
+            Message::Relay {
+                origin,
+                origin_seq,
+                ttl,
+                hops,
+                id,
+            } => Poem {
+                text: PoemText::Library(id),
+                src: origin,
+                seq: origin_seq,
+                hops,
+                ttl,
+            },
+            Message::Fragment {
+                hash,
+                index,
+                count,
+                data,
+            } => {
+                let result = self
+                    .fragments
+                    .lock()
+                    .unwrap()
+                    .push(packet.src, hash, index, count, &data, now);
+                let text = match result {
+                    Ok(Some(text)) => text,
+                    Ok(None) => return None,
+                    Err(e) => {
+                        self.counters.lock().unwrap().dropped += 1;
+                        log::warn!("Dropping poem from {}: {}", packet.src, e);
+                        return None;
+                    }
+                };
+                Poem {
+                    text: self.look_up(text),
+                    src: packet.src,
+                    seq: packet.seq,
+                    hops: 0,
+                    ttl: 0,
+                }
+            }
+            Message::Status { .. } => return None,
+        };
+        if let PoemText::Library(id) = poem.text {
+            if self.library.lock().unwrap().get(id).is_none() {
+                self.counters.lock().unwrap().dropped += 1;
+                log::warn!("Dropping unknown poem {} from {}", id, poem.src);
+                return None;
+            }
+        }
+        // Relays of our own poems come back to us
+        if poem.src == self.device_id.get() || !self.seen.lock().unwrap().insert(poem.src, poem.seq)
+        {
+            self.counters.lock().unwrap().duplicates += 1;
+            return None;
+        }
+        Some(poem)
+    }
+
+    /// Look up a poem that was received as text in the library.
+    fn look_up(&self, text: String) -> PoemText {
+        match self.library.lock().unwrap().find(&text) {
+            Some(id) => PoemText::Library(id),
+            None => PoemText::Received(text),
+        }
+    }
+
+    /// Add a poem that was received as text to the library, if
+    /// `store_received` is set and there's room. This writes to flash, so
+    /// it's called by the display thread and not in `receive`, which runs
+    /// in the radio's callback.
+    pub fn store(&self, poem: Poem) -> Poem {
+        let PoemText::Received(text) = &poem.text else {
+            return poem;
+        };
+        if !self.store_received {
+            return poem;
+        }
+        let mut library = self.library.lock().unwrap();
+        // Another copy may have been stored since it arrived
+        let id = match library.find(text) {
+            Some(id) => id,
+            None => match library.add(text.clone()) {
+                Ok(id) => {
+                    log::info!("Stored received poem as {}", id);
+                    id
+                }
+                Err(e) => {
+                    log::warn!("Not storing received poem: {}", e);
+                    return poem;
+                }
+            },
+        };
+        Poem {
+            text: PoemText::Library(id),
+            ..poem
+        }
+    }
+
+    /// The message to rebroadcast a received poem with, if relaying is
+    /// enabled and the poem has hops left.
+    pub fn relay(&self, poem: &Poem) -> Option<Message> {
+        let PoemText::Library(id) = poem.text else {
+            return None;
+        };
+        if self.relay_ttl.is_none() || poem.ttl == 0 {
+            return None;
+        }
+        Some(Message::Relay {
+            origin: poem.src,
+            origin_seq: poem.seq,
+            ttl: poem.ttl - 1,
+            hops: poem.hops + 1,
+            id,
+        })
+    }
+
+    /// Check whether a new peer uses a device id that we or another peer use.
+    /// Returns `Some` if it does, holding the collision if the id is ours.
+    fn check_collision(&self, peers: &Peers, mac: Mac, device_id: u8) -> Option<Option<Collision>> {
+        // Our own frames don't come back to us
+        let own = device_id == self.device_id.get();
+        let other = if own {
+            self.mac
+        } else {
+            peers.other_mac(mac, device_id)?
+        };
+
+        log::warn!(
+            "Device id {} is used by both {} and {}",
+            device_id,
+            mac_to_string(&other),
+            mac_to_string(&mac)
+        );
+        if !own {
+            return Some(None);
+        }
+
+        let renumber = if self.auto_resolve && self.mac > mac {
+            let taken: Vec<u8> = peers.iter().map(|((_, id), _)| *id).collect();
+            DeviceId::unused_from_mac(&self.mac, &taken)
+        } else {
+            None
+        };
+        if let Some(id) = renumber {
+            log::warn!("Switching to device id {}", id);
+        }
+        Some(Some(Collision {
+            device_id: self.device_id,
+            mac,
+            renumber,
+        }))
+    }
+}
+
+/// Picks the poems a badge broadcasts.
+pub struct Broadcaster {
+    device_id: DeviceId,
+    seq: u16,
+    selector: Selector,
+    /// Seconds between broadcasts.
+    pub send_delay: std::ops::Range<u64>,
+}
+
+impl Broadcaster {
+    pub fn new(device_id: DeviceId, strategy: Strategy) -> Self {
+        Broadcaster {
+            device_id,
+            seq: 0,
+            selector: Selector::new(strategy),
+            send_delay: SEND_DELAY_RANGE,
+        }
+    }
+
+    /// Remember a poem that was heard from another badge.
+    pub fn seen(&mut self, id: u8) {
+        self.selector.seen(id);
+    }
+
+    /// The next poem to broadcast, `None` if the library is empty.
+    pub fn next_poem<R: Rng>(&mut self, rng: &mut R, library: &Library) -> Option<u8> {
+        self.selector.select(library, rng, |_| false)
+    }
+
+    /// How long to wait before the next broadcast.
+    pub fn delay<R: Rng>(&self, rng: &mut R) -> Duration {
+        Duration::from_secs(rng.gen_range(self.send_delay.clone()))
+    }
+
+    /// Packets for the next poem, `None` if the library is empty.
+    pub fn next_packets<R: Rng>(&mut self, rng: &mut R, library: &Library) -> Option<Vec<Packet>> {
+        let id = self.next_poem(rng, library)?;
+        self.packets(id, library)
+    }
+
+    /// Packets for a poem in `library`. Poems every badge has are sent by
+    /// id, poems that were added to this badge as text, since their id
+    /// means nothing to the others. `None` if the poem isn't in the library
+    /// or is too long.
+    pub fn packets(&mut self, poem_id: u8, library: &Library) -> Option<Vec<Packet>> {
+        let text = library.get(poem_id)?;
+        if library.is_shared(poem_id) {
+            Some(vec![self.packet(poem_id)])
+        } else {
+            self.text_packets(text)
+        }
+    }
+
+    /// Packet for a specific poem, by id.
+    pub fn packet(&mut self, poem_id: u8) -> Packet {
+        self.message(Message::Poem { id: poem_id })
+    }
+
+    /// Packets that carry the text of a poem, for badges that don't have it.
+    /// `None` if the poem is too long.
+    pub fn text_packets(&mut self, text: &str) -> Option<Vec<Packet>> {
+        Some(
+            protocol::fragment(text)?
+                .into_iter()
+                .map(|message| self.message(message))
+                .collect(),
+        )
+    }
+
+    /// Status beacon with the battery charge in percent.
+    pub fn status(&mut self, battery: Option<u8>) -> Packet {
+        self.message(Message::Status { battery })
+    }
+
+    /// Packet for any message, like a poem to relay.
+    pub fn message(&mut self, message: Message) -> Packet {
+        let packet = Packet::new(self.device_id.get(), self.seq, message);
+        self.seq = self.seq.wrapping_add(1);
+        packet
+    }
+}
+
+/// Something the display thread should show.
+pub enum Scene {
+    /// "Device x/42 / Waiting for Poetry.." with the counters and the number
+    /// of badges nearby.
+    Waiting,
+    Received(Poem),
+    /// Announces that a random poem will be shown.
+    NoPoem,
+    Random(Poem),
+    /// Warns that another badge uses our device id.
+    Collision(Collision),
+    /// Warns that the battery is low and the badge saves power.
+    LowBattery(Level),
+}
+
+/// What the display thread should do next.
+pub enum Step {
+    Show(Scene),
+    /// Wait at most this long for a poem to arrive and pass it to
+    /// `DisplayLoop::receive`.
+    Listen(Duration),
+}
+
+enum State {
+    Status,
+    Listen,
+    Listened,
+    Random,
+}
+
+/// The display thread's state machine.
+///
+/// Time is passed in as the duration since boot, so the same logic can be
+/// driven by a real or a simulated clock.
+pub struct DisplayLoop {
+    device_id: DeviceId,
+    state: State,
+    last_received: Duration,
+    collision: Option<Collision>,
+    low_battery: Option<Level>,
+    queue: Queue,
+    history: History,
+    /// Picks the random poems.
+    selector: Selector,
+    /// Received poems that were skipped as a repeat.
+    repeats: u32,
+}
+
+impl DisplayLoop {
+    pub fn new(device_id: DeviceId, policy: Policy, now: Duration) -> Self {
+        DisplayLoop {
+            device_id,
+            state: State::Status,
+            last_received: now,
+            collision: None,
+            low_battery: None,
+            queue: Queue::new(policy.clone()),
+            selector: Selector::new(policy.selection.clone()),
+            history: History::new(policy),
+            repeats: 0,
+        }
+    }
+
+    /// Queue a poem that was received, or requested on the console. Repeats
+    /// and poems from badges that send a lot are skipped, except for poems
+    /// requested on the console.
+    pub fn receive(&mut self, now: Duration, poem: Poem) {
+        self.last_received = now;
+        if let PoemText::Library(id) = poem.text {
+            self.selector.seen(id);
+        }
+        if poem.src != self.device_id.get() {
+            if let Err(skip) = self.history.admit(&poem, now) {
+                log::info!("Skipping the poem from {}, {}", poem.src, skip);
+                self.repeats += 1;
+                return;
+            }
+        }
+        if let Some(dropped) = self.queue.push(Priority::Received, poem) {
+            log::info!(
+                "Too many poems queued, dropping the one from {}",
+                dropped.src
+            );
+        }
+    }
+
+    /// Number of poems waiting to be shown.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Received poems that weren't shown, because they were repeats or too
+    /// many were waiting.
+    pub fn skipped(&self) -> u32 {
+        self.repeats + self.queue.dropped
+    }
+
+    /// Whether `scene`, that is being shown, should make way for a queued
+    /// poem.
+    pub fn interrupts(&self, scene: &Scene) -> bool {
+        match scene {
+            Scene::Waiting | Scene::NoPoem => !self.queue.is_empty(),
+            Scene::Random(_) => self.queue.interrupts(Priority::Random),
+            Scene::Received(_) | Scene::Collision(_) | Scene::LowBattery(_) => false,
+        }
+    }
+
+    fn next_poem(&mut self) -> Option<Scene> {
+        let (priority, poem) = self.queue.pop()?;
+        self.history.shown(&poem.text);
+        Some(match priority {
+            Priority::Received => Scene::Received(poem),
+            Priority::Random => Scene::Random(poem),
+        })
+    }
+
+    /// Show a collision warning before the next status screen.
+    pub fn warn(&mut self, collision: Collision) {
+        self.collision = Some(collision);
+    }
+
+    /// Show a low battery warning before the next status screen.
+    pub fn warn_battery(&mut self, level: Level) {
+        self.low_battery = Some(level);
+    }
+
+    /// Random poems are picked from `library`.
+    pub fn step<R: Rng>(&mut self, now: Duration, library: &Library, rng: &mut R) -> Step {
+        match self.state {
+            State::Status => {
+                if let Some(collision) = self.collision.take() {
+                    return Step::Show(Scene::Collision(collision));
+                }
+                if let Some(level) = self.low_battery.take() {
+                    return Step::Show(Scene::LowBattery(level));
+                }
+                // Skip the status screen while poems are waiting
+                if let Some(scene) = self.next_poem() {
+                    return Step::Show(scene);
+                }
+                self.state = State::Listen;
+                Step::Show(Scene::Waiting)
+            }
+            State::Listen => {
+                self.state = State::Listened;
+                Step::Listen(LISTEN_TIMEOUT)
+            }
+            State::Listened => {
+                if let Some(scene) = self.next_poem() {
+                    self.state = State::Status;
+                    Step::Show(scene)
+                } else if now.saturating_sub(self.last_received) > FALLBACK_AFTER {
+                    log::info!("No poem received in the last 10 seconds..");
+                    self.state = State::Random;
+                    Step::Show(Scene::NoPoem)
+                } else {
+                    self.state = State::Listen;
+                    Step::Show(Scene::Waiting)
+                }
+            }
+            State::Random => {
+                self.state = State::Status;
+                // Avoid the poems that were shown recently, unless all were
+                let history = &self.history;
+                let id = self
+                    .selector
+                    .select(library, rng, |id| history.was_shown(&PoemText::Library(id)))
+                    .or_else(|| self.selector.select(library, rng, |_| false));
+                if let Some(id) = id {
+                    let poem = Poem::local(id, self.device_id.get());
+                    self.queue.push(Priority::Random, poem);
+                }
+                Step::Show(self.next_poem().unwrap_or(Scene::Waiting))
+            }
+        }
+    }
+}
+
+/// Boot and run the display loop in real time, receiving poems from `rx`.
+///
+/// `start` is the boot time of the badge, that the peer registry is relative
+/// to. Returns the id to switch to when a collision was auto-resolved, the
+/// badge should store it and reboot.
+pub fn run<S: Screen>(
+    display: &mut S,
+    badge: &Badge,
+    rx: &Receiver<Poem>,
+    policy: Policy,
+    protection: Protection,
+    start: Instant,
+) -> Result<DeviceId, Error> {
+    let mut saver = ScreenSaver::new(protection, start.elapsed());
+    display.set_contrast(saver.protection().contrast)?;
+    boot(display)?;
+
+    let rng = &mut rand::thread_rng();
+    let mut display_loop = DisplayLoop::new(badge.device_id, policy, start.elapsed());
+    let mut battery_low = false;
+    loop {
+        if let Some(collision) = badge.take_collision() {
+            saver.activity(start.elapsed());
+            display_loop.warn(collision);
+        }
+        let battery = *badge.battery.lock().unwrap();
+        let low = battery.is_some_and(|level| level.low);
+        if low != battery_low {
+            battery_low = low;
+            saver.set_battery_low(low);
+            if let Some(level) = battery.filter(|level| level.low) {
+                saver.activity(start.elapsed());
+                display_loop.warn_battery(level);
+            }
+        }
+        let now = start.elapsed();
+        if let Some(mode) = saver.update(now) {
+            log::info!("Display {:?}", mode);
+            display.set_contrast(saver.contrast(mode))?;
+            display.set_on(mode != Mode::Off)?;
+        }
+        let step = display_loop.step(now, &badge.library.lock().unwrap(), rng);
+        match step {
+            Step::Show(scene) => {
+                // Nothing is drawn while the screensaver is on, until a poem
+                // arrives
+                if saver.mode(now) != Mode::Off {
+                    let queued = display_loop.queued();
+                    let shift = saver.shift(now);
+                    scene.render(display, badge, now, queued, shift, || {
+                        // Queue the poems that arrive while the scene is shown
+                        for poem in rx.try_iter() {
+                            saver.activity(start.elapsed());
+                            display_loop.receive(start.elapsed(), badge.store(poem));
+                        }
+                        display_loop.interrupts(&scene)
+                    })?;
+                }
+                // A new id is used even when the warning wasn't shown
+                if let Scene::Collision(Collision {
+                    renumber: Some(id), ..
+                }) = scene
+                {
+                    return Ok(id);
+                }
+            }
+            // Wait for messages, but timeout
+            Step::Listen(timeout) => {
+                if let Ok(poem) = rx.recv_timeout(timeout) {
+                    saver.activity(start.elapsed());
+                    display_loop.receive(start.elapsed(), badge.store(poem));
+                }
+            }
+        }
+    }
+}
+
+fn centered_text_style() -> TextStyle {
+    TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .line_height(LineHeight::Percent(150))
+        .baseline(Baseline::Top)
+        .build()
+}
+
+/// Draw a text centered on an empty screen, moved by `shift`.
+fn show_centered<S: Screen>(display: &mut S, s: &str, shift: Point) -> Result<(), Error> {
+    draw_centered(display, s, shift);
+    display.flush()
+}
+
+/// `s` wrapped to the width of the screen, which is only 32 pixels when the
+/// panel is on its side.
+fn wrap<S: Screen>(display: &S, s: &str) -> String {
+    layout(display, s).text()
+}
+
+/// Whether `s` fits on the screen once it's wrapped.
+fn fits<S: Screen>(display: &S, s: &str) -> bool {
+    layout(display, s).height() <= display.bounding_box().size.height
+}
+
+fn layout<S: Screen>(display: &S, s: &str) -> Layout {
+    Layout::new(
+        &character_style(),
+        &centered_text_style(),
+        &glyphs::normalize(s),
+        display.bounding_box().size.width,
+    )
+}
+
+/// Like `show_centered`, but leaves flushing to the caller.
+fn draw_centered<S: Screen>(display: &mut S, s: &str, shift: Point) {
+    display.clear(BinaryColor::Off).unwrap();
+    let s = wrap(display, s);
+    let mut text = Text::with_text_style(
+        &s,
+        Point::new(0, 0),
+        character_style(),
+        centered_text_style(),
+    );
+    // Since the alignment is center, the bounding box is moved to the left,
+    // so we move it to 0,0 and then translate it to the calculated center
+    text.translate_mut(
+        screen_center(display.bounding_box().size, &text) + shift - text.bounding_box().top_left,
+    );
+    text.draw(display).unwrap();
+}
+
+/// Draw a battery, 9 by 5 pixels, that is filled up to `percent`.
+fn draw_battery<S: Screen>(display: &mut S, top_left: Point, percent: u8) {
+    let on = PrimitiveStyle::with_fill(BinaryColor::On);
+    Rectangle::new(top_left, Size::new(8, 5))
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(display)
+        .unwrap();
+    // The terminal
+    Rectangle::new(top_left + Point::new(8, 1), Size::new(1, 3))
+        .into_styled(on)
+        .draw(display)
+        .unwrap();
+    let filled = (6 * percent.min(100) as u32 + 50) / 100;
+    Rectangle::new(top_left + Point::new(1, 1), Size::new(filled, 3))
+        .into_styled(on)
+        .draw(display)
+        .unwrap();
+}
+
+/// The boot sequence: logo, sub logo and boot text.
+pub fn boot<S: Screen>(display: &mut S) -> Result<(), Error> {
+    // Logo
+    display.clear(BinaryColor::Off).unwrap();
+    let logotype = LOGOTYPE.raw();
+
+    let image = Image::new(&logotype, Point::new(0, 0));
+
+    // Show logo 3 times, unless it doesn't fit on a panel on its side
+    if LOGOTYPE.width <= display.bounding_box().size.width {
+        let mut logo = Sequence::new();
+        for _ in 0..3 {
+            logo = logo.then(effects::up(display.bounding_box().size, image));
+        }
+        animation::play(display, logo)?;
+    }
+
+    show_centered(display, "XIII\nNothing To See Here", Point::zero())?;
+    display.delay(Duration::from_secs(4));
+
+    let s = wrap(
+        display,
+        "Vogon Poetry Transceiver\nVersion: 0x42\nBooting..",
+    );
+    let mut boot_text = Text::with_text_style(
+        &s,
+        Point::new(0, 0),
+        character_style(),
+        centered_text_style(),
+    );
+    boot_text.translate_mut(
+        screen_center(display.bounding_box().size, &boot_text) - boot_text.bounding_box().top_left,
+    );
+
+    animation::play(
+        display,
+        effects::Blink::new(boot_text, 3, Duration::from_millis(500), true),
+    )?;
+
+    display.delay(Duration::from_secs(2));
+    Ok(())
+}
+
+impl Scene {
+    /// Draw the scene, `now` being the time since boot and `queued` the
+    /// number of poems waiting after this one. Status screens are moved by
+    /// `shift`, so they don't burn in. The scene stops early when
+    /// `interrupted` returns true, which is checked before every frame.
+    pub fn render<S: Screen>(
+        &self,
+        display: &mut S,
+        badge: &Badge,
+        now: Duration,
+        queued: usize,
+        shift: Point,
+        mut interrupted: impl FnMut() -> bool,
+    ) -> Result<(), Error> {
+        let library = &badge.library;
+        match self {
+            Scene::Waiting => {
+                let wait = {
+                    let c = badge.counters.lock().unwrap();
+                    let nearby = badge.peers.lock().unwrap().nearby(now);
+                    format!(
+                        "Device {}/{}, {} near\nWaiting for Poetry..\nReceived: {}, sent: {}",
+                        badge.device_id, TOTAL_DEVICES, nearby, c.received, c.sent
+                    )
+                };
+                if !fits(display, &wait) {
+                    scroll(display, &wait, &mut interrupted)?;
+                    return Ok(());
+                }
+                draw_centered(display, &wait, shift);
+                // In the top right corner, right of the first line
+                if let Some(level) = *badge.battery.lock().unwrap() {
+                    let width = display.bounding_box().size.width as i32;
+                    draw_battery(display, Point::new(width - 11, 2) + shift, level.percent);
+                }
+                display.flush()?;
+                pause(display, STATUS_DURATION, &mut interrupted)?;
+            }
+            Scene::Received(poem) => display_poem(
+                display,
+                poem,
+                library,
+                queued,
+                &mut interrupted,
+                &received_from(poem.src, poem.hops),
+            )?,
+            Scene::NoPoem => show_status(
+                display,
+                "No poem received in\nthe last 10 seconds..\nRandomly picking one..",
+                shift,
+                &mut interrupted,
+            )?,
+            Scene::Random(poem) => display_poem(
+                display,
+                poem,
+                library,
+                queued,
+                &mut interrupted,
+                "Random poem:\n",
+            )?,
+            Scene::Collision(collision) => {
+                let action = match collision.renumber {
+                    Some(id) => format!("Switching to id {}..", id),
+                    None => "Please set a new id..".to_string(),
+                };
+                let s = format!(
+                    "Device id {} is also\nused by another badge\n{}",
+                    collision.device_id, action
+                );
+                show_status(display, &s, shift, || false)?;
+            }
+            Scene::LowBattery(level) => {
+                let s = format!(
+                    "Battery low: {}%\n{}.{:02}V\nSaving power..",
+                    level.percent,
+                    level.millivolts / 1000,
+                    level.millivolts % 1000 / 10
+                );
+                show_status(display, &s, shift, || false)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The lines typed before a received poem. `corpus::validate` checks the
+/// poems with the longest ones.
+fn received_from(src: u8, hops: u8) -> String {
+    match hops {
+        0 => format!("Received from: {}/{}\n", src, TOTAL_DEVICES),
+        1 => format!("Received from: {}/{}\nvia 1 hop\n", src, TOTAL_DEVICES),
+        hops => format!(
+            "Received from: {}/{}\nvia {} hops\n",
+            src, TOTAL_DEVICES, hops
+        ),
+    }
+}
+
+/// The line typed before a poem when `queued` more are waiting.
+fn queued_line(queued: usize) -> String {
+    match queued {
+        0 => String::new(),
+        n => format!("queued: {}\n", n),
+    }
+}
+
+/// Show a status text for `STATUS_DURATION`, or scroll it up through the
+/// screen when it doesn't fit, as on a panel on its side.
+fn show_status<S: Screen>(
+    display: &mut S,
+    s: &str,
+    shift: Point,
+    interrupted: impl FnMut() -> bool,
+) -> Result<(), Error> {
+    if !fits(display, s) {
+        return scroll(display, s, interrupted);
+    }
+    show_centered(display, s, shift)?;
+    pause(display, STATUS_DURATION, interrupted)
+}
+
+/// Scroll `s` up through the whole screen once, or until interrupted.
+fn scroll<S: Screen>(
+    display: &mut S,
+    s: &str,
+    interrupted: impl FnMut() -> bool,
+) -> Result<(), Error> {
+    let mut scroller = Scroller::new(s, display.bounding_box());
+    Player::default().play(display, &mut scroller, interrupted)?;
+    Ok(())
+}
+
+/// Keep what is on the screen for `duration`, or until interrupted.
+fn pause<S: Screen>(
+    display: &mut S,
+    duration: Duration,
+    interrupted: impl FnMut() -> bool,
+) -> Result<(), Error> {
+    Player::default().play(display, &mut Pause::new(duration), interrupted)?;
+    Ok(())
+}
+
+fn display_poem<S: Screen>(
+    display: &mut S,
+    poem: &Poem,
+    library: &Mutex<Library>,
+    queued: usize,
+    interrupted: impl FnMut() -> bool,
+    intro_text: &str,
+) -> Result<(), Error> {
+    let text = match &poem.text {
+        PoemText::Library(id) => {
+            log::info!(
+                "Displaying poem id: {}, from {} ({} hops)",
+                id,
+                poem.src,
+                poem.hops
+            );
+            match library.lock().unwrap().get(*id) {
+                Some(text) => text.to_string(),
+                None => {
+                    log::warn!("Poem {} was removed", id);
+                    return Ok(());
+                }
+            }
+        }
+        PoemText::Received(text) => {
+            log::info!("Displaying received poem from {}", poem.src);
+            text.clone()
+        }
+    };
+    display.clear(BinaryColor::Off).unwrap();
+    // Credit the poems the badge was built with. Titles that don't fit on a
+    // line tick by on the top line before the poem is typed.
+    let top_line = Rectangle::new(
+        Point::zero(),
+        Size::new(
+            display.bounding_box().size.width,
+            FONT.character_size.height,
+        ),
+    );
+    let mut ticker = None;
+    let title = match &poem.text {
+        PoemText::Library(id) => corpus::get(*id)
+            .filter(|entry| entry.body == text)
+            .map(|entry| {
+                let credit = entry
+                    .credit()
+                    .map(|credit| format!("by {}\n", credit))
+                    .unwrap_or_default();
+                let title = Ticker::new(entry.title, top_line);
+                if title.fits() {
+                    format!("{}\n{}", entry.title, credit)
+                } else {
+                    ticker = Some(title);
+                    credit
+                }
+            }),
+        PoemText::Received(_) => None,
+    };
+    let s = format!(
+        "{}{}{}{}",
+        intro_text,
+        queued_line(queued),
+        title.unwrap_or_default(),
+        text
+    );
+    let mut animation = Sequence::new();
+    if let Some(ticker) = ticker {
+        animation = animation.then(ticker);
+    }
+    let mut animation = animation
+        .then(effects::TypeText::new(&s))
+        .then(Pause::new(Duration::from_secs(2)));
+    if !Player::default().play(display, &mut animation, interrupted)? {
+        log::info!("Interrupted the poem from {}", poem.src);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{Framebuffer, HeadlessScreen};
+
+    #[test]
+    fn wraps_to_portrait_screens() {
+        let display = Framebuffer::new(Size::new(32, 128));
+        let s = wrap(&display, "Device 12/42, 3 near\nWaiting for Poetry..");
+        assert!(s.lines().count() > 2);
+        // 6 characters of 5 pixels fit
+        assert!(s.lines().all(|line| line.chars().count() <= 6), "{}", s);
+
+        let display = Framebuffer::default();
+        assert_eq!(
+            wrap(&display, "Waiting for Poetry.."),
+            "Waiting for Poetry.."
+        );
+    }
+
+    #[test]
+    fn stores_received_poems_on_the_display_thread() {
+        let mut badge = Badge::new(
+            DeviceId::new(3).unwrap(),
+            [0, 0, 0, 0, 0, 2],
+            Library::new(corpus::poems()),
+            Duration::from_secs(60),
+        );
+        badge.store_received = true;
+        let text = "Oh freddled gruntbuggly";
+        let mut poem = None;
+        for (seq, message) in protocol::fragment(text).unwrap().into_iter().enumerate() {
+            let packet = Packet::new(5, seq as u16, message);
+            let data = packet.serialize();
+            poem = badge.receive([0, 0, 0, 0, 0, 1], None, &data, Duration::ZERO);
+        }
+        let poem = poem.unwrap();
+        assert_eq!(poem.text, PoemText::Received(text.to_string()));
+        assert_eq!(badge.library.lock().unwrap().find(text), None);
+
+        let poem = badge.store(poem);
+        let id = badge.library.lock().unwrap().find(text).unwrap();
+        assert_eq!(poem.text, PoemText::Library(id));
+    }
+
+    #[test]
+    fn renumbers_with_the_display_off() {
+        let mut badge = Badge::new(
+            DeviceId::new(3).unwrap(),
+            [0, 0, 0, 0, 0, 2],
+            Library::new(corpus::poems()),
+            Duration::from_secs(60),
+        );
+        badge.auto_resolve = true;
+        let packet = Packet::new(3, 0, Message::Status { battery: None });
+        badge.receive(
+            [0, 0, 0, 0, 0, 1],
+            None,
+            &packet.serialize(),
+            Duration::ZERO,
+        );
+
+        let (_tx, rx) = std::sync::mpsc::channel();
+        let protection = Protection {
+            off_after: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let mut display = HeadlessScreen::default();
+        let id = run(
+            &mut display,
+            &badge,
+            &rx,
+            Policy::default(),
+            protection,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_ne!(id, badge.device_id);
+    }
+
+    #[test]
+    fn poems_are_validated_with_the_longest_intro() {
+        let intro = received_from(TOTAL_DEVICES, u8::MAX) + &queued_line(u8::MAX as usize);
+        assert_eq!(intro, corpus::validate::INTRO);
+    }
+
+    #[test]
+    fn scrolls_status_screens_that_dont_fit() {
+        let no_poem = "No poem received in\nthe last 10 seconds..\nRandomly picking one..";
+        assert!(fits(&Framebuffer::default(), no_poem));
+        let waiting = "Device 42/42, 42 near\nWaiting for Poetry..\nReceived: 100, sent: 100";
+        assert!(fits(&Framebuffer::default(), waiting));
+
+        // On its side the text enters at the bottom
+        let badge = Badge::new(
+            DeviceId::new(3).unwrap(),
+            [0, 0, 0, 0, 0, 2],
+            Library::new(corpus::poems()),
+            Duration::from_secs(60),
+        );
+        let mut display = Framebuffer::new(Size::new(32, 128));
+        assert!(!fits(&display, no_poem));
+        let mut frames = 0;
+        Scene::NoPoem
+            .render(
+                &mut display,
+                &badge,
+                Duration::ZERO,
+                0,
+                Point::zero(),
+                || {
+                    frames += 1;
+                    frames > 20
+                },
+            )
+            .unwrap();
+        let lit = |y: u32, height: u32| {
+            (y..y + height)
+                .flat_map(|y| (0..32).map(move |x| (x, y)))
+                .filter(|(x, y)| display.pixel(*x, *y))
+                .count()
+        };
+        assert_eq!(lit(0, 100), 0);
+        assert!(lit(108, 20) > 0);
+    }
+
+    #[test]
+    fn long_titles_tick_by() {
+        let entry = corpus::get(0).unwrap();
+        assert!(entry.title.len() * 5 > 128);
+        let library = Mutex::new(Library::new(corpus::poems()));
+        let mut display = Framebuffer::default();
+        let mut frames = 0;
+        display_poem(
+            &mut display,
+            &Poem::local(0, 1),
+            &library,
+            0,
+            || {
+                frames += 1;
+                frames > 20
+            },
+            "Random poem:\n",
+        )
+        .unwrap();
+        // Only the start of the title is on the screen, entering on the right
+        let lit = |x: i32, y: i32, width: u32, height: u32| {
+            Rectangle::new(Point::new(x, y), Size::new(width, height))
+                .points()
+                .filter(|p| display.pixel(p.x as u32, p.y as u32))
+                .count()
+        };
+        assert!(lit(98, 0, 30, 7) > 0);
+        assert_eq!(lit(0, 0, 98, 7), 0);
+        assert_eq!(lit(0, 7, 128, 25), 0);
+    }
+}
@@ -0,0 +1,31 @@
+use embedded_graphics::geometry::{Dimensions, Point, Size};
+
+/// Where `d` starts when it is centered horizontally on a screen of `screen`.
+pub fn screen_center_x<D>(screen: Size, d: &D) -> i32
+where
+    D: Dimensions,
+{
+    ((screen.width / 2) as i32) - ((d.bounding_box().size.width / 2) as i32)
+}
+
+pub fn screen_center_y<D>(screen: Size, d: &D) -> i32
+where
+    D: Dimensions,
+{
+    ((screen.height / 2) as i32) - ((d.bounding_box().size.height / 2) as i32)
+}
+
+/// The top left corner of `d` when it is centered on a screen of `screen`.
+pub fn screen_center<D>(screen: Size, d: &D) -> Point
+where
+    D: Dimensions,
+{
+    Point::new(screen_center_x(screen, d), screen_center_y(screen, d))
+}
+
+pub fn mac_to_string(mac: &[u8]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}